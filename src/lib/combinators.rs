@@ -4,7 +4,7 @@ pub use nom::{
     branch::alt,
     bytes::complete::{is_a, tag, take_while},
     character::complete::{anychar, line_ending, multispace0, one_of, space0, space1},
-    combinator::{eof, into, map, map_res, opt, recognize, value, verify},
+    combinator::{eof, into, map, map_res, opt, recognize, rest_len, value, verify},
     error::ParseError,
     multi::{count, many1, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
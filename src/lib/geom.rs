@@ -0,0 +1,507 @@
+use super::grid;
+use std::fmt;
+use std::ops::{Add, AddAssign, Index, IndexMut, Mul, Neg, Sub, SubAssign};
+
+/// A displacement between two points in N dimensions.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Vector<const N: usize>(pub [i64; N]);
+
+/// A location in N dimensions.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Point<const N: usize>(pub [i64; N]);
+
+impl<const N: usize> Vector<N> {
+    pub const ZERO: Vector<N> = Vector([0; N]);
+
+    /// The vector of length one along axis.
+    pub fn unit(axis: usize) -> Vector<N> {
+        let mut v = [0; N];
+        v[axis] = 1;
+        Vector(v)
+    }
+
+    pub fn dot(&self, other: &Vector<N>) -> i64 {
+        self.0.iter().zip(other.0.iter()).map(|(a, b)| a * b).sum()
+    }
+
+    pub fn manhattan(&self) -> i64 {
+        self.0.iter().map(|v| v.abs()).sum()
+    }
+
+    pub fn chebyshev(&self) -> i64 {
+        self.0.iter().map(|v| v.abs()).max().unwrap_or(0)
+    }
+
+    pub fn norm_squared(&self) -> i64 {
+        self.dot(self)
+    }
+
+    /// Each component replaced by its sign.
+    pub fn signum(&self) -> Vector<N> {
+        Vector(self.0.map(i64::signum))
+    }
+
+    /// The shortest integer vector pointing the same way, i.e. self divided by
+    /// the gcd of its components.
+    pub fn reduced(&self) -> Vector<N> {
+        fn gcd(a: i64, b: i64) -> i64 {
+            if b == 0 {
+                a
+            } else {
+                gcd(b, a % b)
+            }
+        }
+
+        let g = self.0.iter().fold(0, |g, v| gcd(g, v.abs()));
+        if g == 0 {
+            return *self;
+        }
+        Vector(self.0.map(|v| v / g))
+    }
+}
+
+impl<const N: usize> Point<N> {
+    pub const ORIGIN: Point<N> = Point([0; N]);
+
+    pub fn manhattan(&self, other: &Point<N>) -> i64 {
+        (*self - *other).manhattan()
+    }
+
+    pub fn chebyshev(&self, other: &Point<N>) -> i64 {
+        (*self - *other).chebyshev()
+    }
+
+    pub fn distance_squared(&self, other: &Point<N>) -> i64 {
+        (*self - *other).norm_squared()
+    }
+
+    /// The points differing by one along a single axis.
+    pub fn neighbors(&self) -> impl Iterator<Item = Point<N>> + '_ {
+        (0..N).flat_map(move |axis| {
+            let d = Vector::unit(axis);
+            [*self - d, *self + d]
+        })
+    }
+}
+
+macro_rules! impl_index {
+    ($t:ident) => {
+        impl<const N: usize> Index<usize> for $t<N> {
+            type Output = i64;
+
+            fn index(&self, i: usize) -> &i64 {
+                &self.0[i]
+            }
+        }
+
+        impl<const N: usize> IndexMut<usize> for $t<N> {
+            fn index_mut(&mut self, i: usize) -> &mut i64 {
+                &mut self.0[i]
+            }
+        }
+
+        impl<const N: usize> From<[i64; N]> for $t<N> {
+            fn from(v: [i64; N]) -> Self {
+                $t(v)
+            }
+        }
+
+        impl<const N: usize> From<$t<N>> for [i64; N] {
+            fn from(v: $t<N>) -> Self {
+                v.0
+            }
+        }
+
+        impl<const N: usize> Default for $t<N> {
+            fn default() -> Self {
+                $t([0; N])
+            }
+        }
+
+        impl<const N: usize> fmt::Debug for $t<N> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "(")?;
+                for (i, v) in self.0.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, ")")
+            }
+        }
+    };
+}
+
+impl_index!(Vector);
+impl_index!(Point);
+
+fn zip_with<const N: usize>(a: [i64; N], b: [i64; N], f: impl Fn(i64, i64) -> i64) -> [i64; N] {
+    let mut ret = a;
+    for (r, b) in ret.iter_mut().zip(b) {
+        *r = f(*r, b);
+    }
+    ret
+}
+
+impl<const N: usize> Add for Vector<N> {
+    type Output = Vector<N>;
+
+    fn add(self, other: Vector<N>) -> Vector<N> {
+        Vector(zip_with(self.0, other.0, |a, b| a + b))
+    }
+}
+
+impl<const N: usize> Sub for Vector<N> {
+    type Output = Vector<N>;
+
+    fn sub(self, other: Vector<N>) -> Vector<N> {
+        Vector(zip_with(self.0, other.0, |a, b| a - b))
+    }
+}
+
+impl<const N: usize> Neg for Vector<N> {
+    type Output = Vector<N>;
+
+    fn neg(self) -> Vector<N> {
+        Vector(self.0.map(|v| -v))
+    }
+}
+
+impl<const N: usize> Mul<i64> for Vector<N> {
+    type Output = Vector<N>;
+
+    fn mul(self, k: i64) -> Vector<N> {
+        Vector(self.0.map(|v| v * k))
+    }
+}
+
+impl<const N: usize> AddAssign for Vector<N> {
+    fn add_assign(&mut self, other: Vector<N>) {
+        *self = *self + other;
+    }
+}
+
+impl<const N: usize> SubAssign for Vector<N> {
+    fn sub_assign(&mut self, other: Vector<N>) {
+        *self = *self - other;
+    }
+}
+
+impl<const N: usize> Add<Vector<N>> for Point<N> {
+    type Output = Point<N>;
+
+    fn add(self, v: Vector<N>) -> Point<N> {
+        Point(zip_with(self.0, v.0, |a, b| a + b))
+    }
+}
+
+impl<const N: usize> Sub<Vector<N>> for Point<N> {
+    type Output = Point<N>;
+
+    fn sub(self, v: Vector<N>) -> Point<N> {
+        Point(zip_with(self.0, v.0, |a, b| a - b))
+    }
+}
+
+impl<const N: usize> Sub for Point<N> {
+    type Output = Vector<N>;
+
+    fn sub(self, other: Point<N>) -> Vector<N> {
+        Vector(zip_with(self.0, other.0, |a, b| a - b))
+    }
+}
+
+impl<const N: usize> AddAssign<Vector<N>> for Point<N> {
+    fn add_assign(&mut self, v: Vector<N>) {
+        *self = *self + v;
+    }
+}
+
+impl<const N: usize> SubAssign<Vector<N>> for Point<N> {
+    fn sub_assign(&mut self, v: Vector<N>) {
+        *self = *self - v;
+    }
+}
+
+impl From<grid::Point> for Point<2> {
+    fn from(p: grid::Point) -> Self {
+        Point([p.x as i64, p.y as i64])
+    }
+}
+
+impl TryFrom<Point<2>> for grid::Point {
+    type Error = ();
+
+    /// Fails if either coordinate is negative or doesn't fit in a usize.
+    fn try_from(p: Point<2>) -> Result<Self, Self::Error> {
+        let x = p[0].try_into().map_err(|_| ())?;
+        let y = p[1].try_into().map_err(|_| ())?;
+        Ok(grid::Point::new(x, y))
+    }
+}
+
+/// The smallest axis-aligned box containing a set of points. Both corners are
+/// inclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BoundingBox<const N: usize> {
+    pub min: Point<N>,
+    pub max: Point<N>,
+}
+
+impl<const N: usize> BoundingBox<N> {
+    /// Returns None if there are no points.
+    pub fn from_points<I: IntoIterator<Item = Point<N>>>(points: I) -> Option<BoundingBox<N>> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut ret = BoundingBox {
+            min: first,
+            max: first,
+        };
+        points.for_each(|p| ret.extend(p));
+        Some(ret)
+    }
+
+    /// Grows the box to include p.
+    pub fn extend(&mut self, p: Point<N>) {
+        self.min = Point(zip_with(self.min.0, p.0, i64::min));
+        self.max = Point(zip_with(self.max.0, p.0, i64::max));
+    }
+
+    /// Number of points along each axis.
+    pub fn size(&self) -> Vector<N> {
+        self.max - self.min + Vector([1; N])
+    }
+}
+
+/// A rotation that maps the axes onto the axes, stored as a signed permutation
+/// matrix with determinant 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Rotation<const N: usize> {
+    m: [[i64; N]; N],
+}
+
+impl<const N: usize> Rotation<N> {
+    pub const IDENTITY: Rotation<N> = Rotation {
+        m: identity_matrix(),
+    };
+
+    /// All N! * 2^(N-1) rotations, starting with the identity.
+    pub fn all() -> impl Iterator<Item = Rotation<N>> {
+        permutations(N).into_iter().flat_map(|perm| {
+            (0..1u32 << N).filter_map(move |signs| {
+                let mut m = [[0; N]; N];
+                for (i, &col) in perm.iter().enumerate() {
+                    m[i][col] = if signs & (1 << i) != 0 { -1 } else { 1 };
+                }
+                Rotation::from_matrix(m)
+            })
+        })
+    }
+
+    /// Returns None unless m is a signed permutation matrix with determinant 1.
+    pub fn from_matrix(m: [[i64; N]; N]) -> Option<Rotation<N>> {
+        // the column holding each row's nonzero entry.
+        let mut perm = [0; N];
+        let mut sign = 1;
+        for (i, row) in m.iter().enumerate() {
+            let mut nonzero = row.iter().enumerate().filter(|(_, &v)| v != 0);
+            let (col, &v) = nonzero.next()?;
+            if nonzero.next().is_some() || v.abs() != 1 {
+                return None;
+            }
+            perm[i] = col;
+            sign *= v;
+        }
+
+        let mut seen = [false; N];
+        for &col in perm.iter() {
+            if std::mem::replace(&mut seen[col], true) {
+                return None;
+            }
+        }
+
+        if sign * permutation_sign(&perm) != 1 {
+            return None;
+        }
+
+        Some(Rotation { m })
+    }
+
+    pub fn matrix(&self) -> [[i64; N]; N] {
+        self.m
+    }
+
+    pub fn apply(&self, v: Vector<N>) -> Vector<N> {
+        Vector(self.m.map(|row| Vector(row).dot(&v)))
+    }
+
+    /// Rotates p about the origin.
+    pub fn apply_point(&self, p: Point<N>) -> Point<N> {
+        Point::ORIGIN + self.apply(p - Point::ORIGIN)
+    }
+
+    /// The rotation that applies other first, then self.
+    pub fn compose(&self, other: &Rotation<N>) -> Rotation<N> {
+        let mut m = [[0; N]; N];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = (0..N).map(|k| self.m[i][k] * other.m[k][j]).sum();
+            }
+        }
+        Rotation { m }
+    }
+
+    pub fn inverse(&self) -> Rotation<N> {
+        // rotation matrices are orthogonal, so the transpose is the inverse.
+        let mut m = [[0; N]; N];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().enumerate() {
+                *v = self.m[j][i];
+            }
+        }
+        Rotation { m }
+    }
+}
+
+impl<const N: usize> Default for Rotation<N> {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl<const N: usize> TryFrom<[[i64; N]; N]> for Rotation<N> {
+    type Error = ();
+
+    fn try_from(m: [[i64; N]; N]) -> Result<Self, Self::Error> {
+        Rotation::from_matrix(m).ok_or(())
+    }
+}
+
+impl<const N: usize> From<Rotation<N>> for [[i64; N]; N] {
+    fn from(r: Rotation<N>) -> Self {
+        r.m
+    }
+}
+
+const fn identity_matrix<const N: usize>() -> [[i64; N]; N] {
+    let mut m = [[0; N]; N];
+    let mut i = 0;
+    while i < N {
+        m[i][i] = 1;
+        i += 1;
+    }
+    m
+}
+
+// All orderings of 0..n, in lexicographic order.
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![Vec::new()];
+    }
+
+    let mut ret = Vec::new();
+    for first in 0..n {
+        for rest in permutations(n - 1) {
+            let mut p = vec![first];
+            p.extend(rest.iter().map(|&v| if v >= first { v + 1 } else { v }));
+            ret.push(p);
+        }
+    }
+    ret
+}
+
+// 1 for an even permutation, -1 for an odd one.
+fn permutation_sign(perm: &[usize]) -> i64 {
+    let mut inversions = 0;
+    for (i, a) in perm.iter().enumerate() {
+        inversions += perm[i + 1..].iter().filter(|&b| b < a).count();
+    }
+    if inversions % 2 == 0 {
+        1
+    } else {
+        -1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic_test() {
+        let a = Point([1, 2, 3]);
+        let b = Point([-2, 6, 3]);
+        let d = b - a;
+        assert_eq!(d, Vector([-3, 4, 0]));
+        assert_eq!(a + d, b);
+        assert_eq!(-d * 2, Vector([6, -8, 0]));
+        assert_eq!(a.manhattan(&b), 7);
+        assert_eq!(a.chebyshev(&b), 4);
+        assert_eq!(a.distance_squared(&b), 25);
+        assert_eq!(Vector([4, -6]).reduced(), Vector([2, -3]));
+        assert_eq!(a.neighbors().count(), 6);
+    }
+
+    #[test]
+    fn bounding_box_test() {
+        let points = [Point([1, 5]), Point([-3, 2]), Point([4, -1])];
+        let bb = BoundingBox::from_points(points).unwrap();
+        assert_eq!(bb.min, Point([-3, -1]));
+        assert_eq!(bb.max, Point([4, 5]));
+        assert_eq!(bb.size(), Vector([8, 7]));
+        assert!(BoundingBox::<2>::from_points([]).is_none());
+    }
+
+    #[test]
+    fn rotation_test() {
+        assert_eq!(Rotation::<2>::all().count(), 4);
+        let all: Vec<_> = Rotation::<3>::all().collect();
+        assert_eq!(all.len(), 24);
+        assert_eq!(all[0], Rotation::IDENTITY);
+
+        let mut distinct = all.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 24);
+
+        let v = Vector([1, 2, 3]);
+        for a in all.iter() {
+            assert_eq!(a.compose(&a.inverse()), Rotation::IDENTITY);
+            assert_eq!(a.inverse().compose(a), Rotation::IDENTITY);
+            assert_eq!(Rotation::from_matrix(a.matrix()), Some(*a));
+            assert_eq!(a.apply(v).norm_squared(), v.norm_squared());
+            for b in all.iter() {
+                assert!(all.contains(&a.compose(b)));
+            }
+        }
+
+        // a quarter turn about z, and back.
+        let quarter = Rotation::from_matrix([[0, -1, 0], [1, 0, 0], [0, 0, 1]]).unwrap();
+        assert_eq!(quarter.apply(Vector([2, 1, 5])), Vector([-1, 2, 5]));
+        let p = Point([3, -4, 7]);
+        let back = quarter.compose(&quarter).compose(&quarter);
+        assert_eq!(back.compose(&quarter), Rotation::IDENTITY);
+        assert_eq!(quarter.inverse(), back);
+        assert_eq!(back.apply_point(quarter.apply_point(p)), p);
+
+        // the same turn in the plane, then a 4D one.
+        let quarter = Rotation::from_matrix([[0, -1], [1, 0]]).unwrap();
+        assert_eq!(quarter.apply_point(Point([2, 1])), Point([-1, 2]));
+        assert_eq!(Rotation::<4>::all().count(), 192);
+
+        // a reflection and a non-permutation aren't rotations.
+        assert!(Rotation::from_matrix([[0, 1], [1, 0]]).is_none());
+        assert!(Rotation::from_matrix([[-1, 0, 0], [0, 1, 0], [0, 0, 1]]).is_none());
+        assert!(Rotation::from_matrix([[1, 1, 0], [0, 1, 0], [0, 0, 1]]).is_none());
+    }
+
+    #[test]
+    fn grid_point_test() {
+        let p = grid::Point::new(3, 7);
+        let q: Point<2> = p.into();
+        assert_eq!(q, Point([3, 7]));
+        assert_eq!(grid::Point::try_from(q), Ok(p));
+        assert!(grid::Point::try_from(Point([-1, 0])).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use std::fmt;
+
+/// Integers that can bound a Range.
+pub trait Bound: Copy + Ord {
+    fn succ(self) -> Self;
+    fn pred(self) -> Self;
+    /// Number of integers in [start, end). end must not be less than start.
+    fn steps(start: Self, end: Self) -> u64;
+}
+
+macro_rules! impl_bound {
+    ($($t:ty),*) => {
+        $(
+            impl Bound for $t {
+                fn succ(self) -> Self {
+                    self + 1
+                }
+
+                fn pred(self) -> Self {
+                    self - 1
+                }
+
+                fn steps(start: Self, end: Self) -> u64 {
+                    (end - start) as u64
+                }
+            }
+        )*
+    };
+}
+
+impl_bound!(i32, i64, isize, u32, u64, usize);
+
+// Treats None as positive infinity.
+fn min_end<T: Bound>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn max_end<T: Bound>(a: Option<T>, b: Option<T>) -> Option<T> {
+    Some(a?.max(b?))
+}
+
+/// A half-open range [start, end) of integers, or [start, inf) if end is None.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Range<T> {
+    pub start: T,
+    pub end: Option<T>,
+}
+
+impl<T: Bound> Range<T> {
+    pub fn half_open(start: T, end: T) -> Self {
+        Range {
+            start,
+            end: Some(end),
+        }
+    }
+
+    pub fn inclusive(start: T, last: T) -> Self {
+        Range::half_open(start, last.succ())
+    }
+
+    pub fn unbounded(start: T) -> Self {
+        Range { start, end: None }
+    }
+
+    /// The largest value in the range, if it is bounded.
+    pub fn last(&self) -> Option<T> {
+        self.end.map(T::pred)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        matches!(self.end, Some(end) if end <= self.start)
+    }
+
+    /// Number of values in the range, or None if it is unbounded.
+    pub fn len(&self) -> Option<u64> {
+        let end = self.end?;
+        if end <= self.start {
+            return Some(0);
+        }
+        Some(T::steps(self.start, end))
+    }
+
+    pub fn contains(&self, v: T) -> bool {
+        v >= self.start && self.end.is_none_or(|end| v < end)
+    }
+
+    pub fn intersection(&self, other: &Range<T>) -> Option<Range<T>> {
+        let r = Range {
+            start: self.start.max(other.start),
+            end: min_end(self.end, other.end),
+        };
+        Some(r).filter(|r| !r.is_empty())
+    }
+
+    pub fn overlaps(&self, other: &Range<T>) -> bool {
+        self.intersection(other).is_some()
+    }
+
+    /// Splits self into the parts below, inside and above other. Any of them
+    /// may be empty.
+    pub fn split(&self, other: &Range<T>) -> (Range<T>, Range<T>, Range<T>) {
+        let empty = Range::half_open(self.start, self.start);
+
+        let low_end = min_end(Some(other.start), self.end).unwrap();
+        let low = Range::half_open(self.start, low_end.max(self.start));
+        let overlap = self.intersection(other).unwrap_or(empty);
+        let high = match other.end {
+            Some(end) => Range {
+                start: end.max(self.start),
+                end: self.end,
+            },
+            None => empty,
+        };
+
+        (low, overlap, high)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Range<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.end {
+            Some(end) => write!(f, "[{:?}..{:?})", self.start, end),
+            None => write!(f, "[{:?}..)", self.start),
+        }
+    }
+}
+
+/// A set of integers stored as sorted, disjoint ranges with gaps between them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IntervalSet<T> {
+    ranges: Vec<Range<T>>,
+}
+
+impl<T: Bound> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet { ranges: Vec::new() }
+    }
+
+    pub fn ranges(&self) -> &[Range<T>] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn insert(&mut self, r: Range<T>) {
+        if !r.is_empty() {
+            self.ranges.push(r);
+            self.normalize();
+        }
+    }
+
+    /// Total number of values in the set, or None if it is unbounded.
+    pub fn measure(&self) -> Option<u64> {
+        self.ranges.iter().map(|r| r.len()).sum()
+    }
+
+    pub fn intersection(&self, other: &IntervalSet<T>) -> IntervalSet<T> {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (&self.ranges[i], &other.ranges[j]);
+            ranges.extend(a.intersection(b));
+
+            // whichever ends first can't overlap anything further along.
+            if min_end(a.end, b.end) == a.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+
+        IntervalSet { ranges }
+    }
+
+    pub fn difference(&self, other: &IntervalSet<T>) -> IntervalSet<T> {
+        let mut ranges = Vec::new();
+        for a in self.ranges.iter() {
+            let mut rest = Some(*a);
+            for b in other.ranges.iter() {
+                let cur = match rest {
+                    Some(cur) if cur.overlaps(b) => cur,
+                    _ => continue,
+                };
+
+                let (low, _, high) = cur.split(b);
+                if !low.is_empty() {
+                    ranges.push(low);
+                }
+                rest = Some(high).filter(|r| !r.is_empty());
+            }
+            ranges.extend(rest);
+        }
+
+        IntervalSet { ranges }
+    }
+
+    fn normalize(&mut self) {
+        self.ranges.retain(|r| !r.is_empty());
+        self.ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range<T>> = Vec::with_capacity(self.ranges.len());
+        for r in self.ranges.drain(..) {
+            match merged.last_mut() {
+                Some(last) if last.end.is_none_or(|end| r.start <= end) => {
+                    last.end = max_end(last.end, r.end);
+                }
+                _ => merged.push(r),
+            }
+        }
+        self.ranges = merged;
+    }
+}
+
+impl<T: Bound> Default for IntervalSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Bound> FromIterator<Range<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Range<T>>>(iter: I) -> Self {
+        let mut ret = IntervalSet {
+            ranges: iter.into_iter().collect(),
+        };
+        ret.normalize();
+        ret
+    }
+}
+
+/// An axis-aligned box made of one range per dimension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct IntervalBox<T, const N: usize> {
+    pub ranges: [Range<T>; N],
+}
+
+impl<T: Bound, const N: usize> IntervalBox<T, N> {
+    pub fn new(ranges: [Range<T>; N]) -> Self {
+        IntervalBox { ranges }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.iter().any(|r| r.is_empty())
+    }
+
+    /// Number of points in the box, or None if it is unbounded.
+    pub fn volume(&self) -> Option<u64> {
+        if self.is_empty() {
+            return Some(0);
+        }
+        self.ranges.iter().map(|r| r.len()).product()
+    }
+
+    pub fn contains(&self, p: [T; N]) -> bool {
+        self.ranges.iter().zip(p).all(|(r, v)| r.contains(v))
+    }
+
+    pub fn intersection(&self, other: &Self) -> Option<Self> {
+        let mut ranges = self.ranges;
+        for (r, o) in ranges.iter_mut().zip(other.ranges.iter()) {
+            *r = r.intersection(o)?;
+        }
+        Some(IntervalBox { ranges })
+    }
+
+    /// The parts of self outside other, as disjoint boxes.
+    pub fn difference(&self, other: &Self) -> Vec<Self> {
+        if self.intersection(other).is_none() {
+            return vec![*self];
+        }
+
+        // peel off the slabs below and above other one axis at a time, then
+        // narrow that axis to the overlap before moving on to the next.
+        let mut ret = Vec::new();
+        let mut rest = *self;
+        for axis in 0..N {
+            let (low, overlap, high) = rest.ranges[axis].split(&other.ranges[axis]);
+            for part in [low, high] {
+                if !part.is_empty() {
+                    let mut b = rest;
+                    b.ranges[axis] = part;
+                    ret.push(b);
+                }
+            }
+            rest.ranges[axis] = overlap;
+        }
+
+        ret
+    }
+}
+
+impl<T: Bound, const N: usize> From<[Range<T>; N]> for IntervalBox<T, N> {
+    fn from(ranges: [Range<T>; N]) -> Self {
+        IntervalBox::new(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[(i32, i32)]) -> IntervalSet<i32> {
+        ranges
+            .iter()
+            .map(|&(s, e)| Range::half_open(s, e))
+            .collect()
+    }
+
+    #[test]
+    fn range_test() {
+        let r = Range::inclusive(2, 5);
+        assert_eq!(r, Range::half_open(2, 6));
+        assert_eq!(r.last(), Some(5));
+        assert_eq!(r.len(), Some(4));
+
+        let u = Range::unbounded(4);
+        assert_eq!(u.len(), None);
+        assert!(u.contains(1000));
+        assert_eq!(r.intersection(&u), Some(Range::half_open(4, 6)));
+        assert_eq!(
+            r.split(&u),
+            (
+                Range::half_open(2, 4),
+                Range::half_open(4, 6),
+                Range::half_open(2, 2)
+            )
+        );
+        assert!(!r.overlaps(&Range::half_open(6, 9)));
+    }
+
+    #[test]
+    fn interval_set_test() {
+        let a = set(&[(0, 5), (3, 8), (10, 12)]);
+        assert_eq!(a.ranges(), set(&[(0, 8), (10, 12)]).ranges());
+        assert_eq!(a.measure(), Some(10));
+
+        let b = set(&[(4, 11)]);
+        assert_eq!(a.intersection(&b), set(&[(4, 8), (10, 11)]));
+        assert_eq!(a.difference(&b), set(&[(0, 4), (11, 12)]));
+        assert_eq!(b.difference(&a), set(&[(8, 10)]));
+
+        let mut c = IntervalSet::new();
+        c.insert(Range::unbounded(20));
+        c.insert(Range::half_open(18, 20));
+        assert_eq!(c.ranges(), &[Range::unbounded(18)]);
+        assert_eq!(c.measure(), None);
+        assert_eq!(a.difference(&c), a);
+        assert!(c.difference(&c).is_empty());
+    }
+
+    #[test]
+    fn interval_box_test() {
+        let a = IntervalBox::new([Range::half_open(0, 10); 3]);
+        let b = IntervalBox::new([Range::half_open(5, 15); 3]);
+        assert_eq!(a.volume(), Some(1000));
+        assert_eq!(a.intersection(&b).unwrap().volume(), Some(125));
+
+        let diff = a.difference(&b);
+        assert_eq!(diff.len(), 3);
+        assert_eq!(diff.iter().map(|d| d.volume().unwrap()).sum::<u64>(), 875);
+        assert!(diff.iter().all(|d| d.intersection(&b).is_none()));
+        assert!(a.contains([0, 9, 5]) && !a.contains([0, 10, 5]));
+    }
+}
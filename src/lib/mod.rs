@@ -1,4 +1,7 @@
 pub mod combinators;
+pub mod geom;
 pub mod grid;
 pub mod heapentry;
+pub mod interval;
 pub mod io;
+pub mod ratio;
@@ -0,0 +1,147 @@
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul};
+
+/// A non-negative fraction, always kept in lowest terms so equal values
+/// compare equal. Arithmetic panics if a result doesn't fit in a u128.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Ratio {
+    numer: u128,
+    denom: u128,
+}
+
+fn gcd(a: u128, b: u128) -> u128 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+impl Ratio {
+    pub const ZERO: Ratio = Ratio { numer: 0, denom: 1 };
+    pub const ONE: Ratio = Ratio { numer: 1, denom: 1 };
+
+    pub fn new(numer: u128, denom: u128) -> Ratio {
+        assert!(denom != 0, "ratio with a zero denominator");
+        let g = gcd(numer, denom);
+        Ratio {
+            numer: numer / g,
+            denom: denom / g,
+        }
+    }
+
+    pub fn to_f64(self) -> f64 {
+        self.numer as f64 / self.denom as f64
+    }
+
+    /// None if the sum doesn't fit in a u128.
+    pub fn checked_add(self, other: Ratio) -> Option<Ratio> {
+        // over the lcm of the denominators, to keep the numbers small.
+        let g = gcd(self.denom, other.denom);
+        let denom = (self.denom / g).checked_mul(other.denom)?;
+        let a = self.numer.checked_mul(other.denom / g)?;
+        let b = other.numer.checked_mul(self.denom / g)?;
+        Some(Ratio::new(a.checked_add(b)?, denom))
+    }
+
+    /// None if the product doesn't fit in a u128.
+    pub fn checked_mul(self, other: Ratio) -> Option<Ratio> {
+        // cancel crosswise first so the products stay as small as they can.
+        let a = gcd(self.numer, other.denom);
+        let b = gcd(other.numer, self.denom);
+        Some(Ratio::new(
+            (self.numer / a).checked_mul(other.numer / b)?,
+            (self.denom / b).checked_mul(other.denom / a)?,
+        ))
+    }
+}
+
+impl Add for Ratio {
+    type Output = Ratio;
+
+    fn add(self, other: Ratio) -> Ratio {
+        self.checked_add(other).expect("ratio overflowed a u128")
+    }
+}
+
+impl AddAssign for Ratio {
+    fn add_assign(&mut self, other: Ratio) {
+        *self = *self + other;
+    }
+}
+
+impl Mul for Ratio {
+    type Output = Ratio;
+
+    fn mul(self, other: Ratio) -> Ratio {
+        self.checked_mul(other).expect("ratio overflowed a u128")
+    }
+}
+
+impl Sum for Ratio {
+    fn sum<I: Iterator<Item = Ratio>>(iter: I) -> Ratio {
+        iter.fold(Ratio::ZERO, |a, b| a + b)
+    }
+}
+
+impl Default for Ratio {
+    fn default() -> Self {
+        Ratio::ZERO
+    }
+}
+
+impl From<u128> for Ratio {
+    fn from(n: u128) -> Ratio {
+        Ratio { numer: n, denom: 1 }
+    }
+}
+
+impl fmt::Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.denom {
+            1 => write!(f, "{}", self.numer),
+            d => write!(f, "{}/{}", self.numer, d),
+        }
+    }
+}
+
+impl fmt::Debug for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ratio_test() {
+        let half = Ratio::new(2, 4);
+        assert_eq!(half, Ratio::new(1, 2));
+        assert_eq!((half.numer, half.denom), (1, 2));
+        assert_eq!(half + Ratio::new(1, 3), Ratio::new(5, 6));
+        assert_eq!(half * Ratio::new(2, 3), Ratio::new(1, 3));
+        assert_eq!(Ratio::ZERO * half, Ratio::ZERO);
+        assert_eq!(
+            [Ratio::new(1, 2), Ratio::new(1, 4), Ratio::new(1, 4)]
+                .into_iter()
+                .sum::<Ratio>(),
+            Ratio::ONE
+        );
+
+        assert_eq!(half.to_string(), "1/2");
+        assert_eq!(Ratio::from(3).to_string(), "3");
+        assert_eq!(half.to_f64(), 0.5);
+
+        // sums of powers of 27 stay exact well past a u64.
+        let third_power = |n| Ratio::new(1, 27u128.pow(n));
+        let sum: Ratio = (1..=20).map(third_power).sum();
+        assert_eq!(sum.denom, 27u128.pow(20));
+        let tiny = Ratio::new(1, u128::MAX);
+        assert_eq!(tiny.checked_mul(tiny), None);
+        assert_eq!(tiny.checked_add(Ratio::new(1, 2)), None);
+        assert_eq!(half.checked_add(half), Some(Ratio::ONE));
+    }
+}
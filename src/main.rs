@@ -6,15 +6,192 @@ mod solutions;
 
 use lib::io::load_puzzle_input;
 use std::env;
+use std::fs;
 
 fn main() {
     let mut args = env::args();
     args.next();
-    let day: usize = args
-        .next()
-        .expect("not enough args")
-        .parse()
-        .expect("failed to parse arg");
+    let first = args.next().expect("not enough args");
+
+    // `displays [file [segments digit...]]` reads seven-segment displays, day
+    // 8's input by default, or displays on a font with that many segments
+    // where each digit lists the segments it lights.
+    if first == "displays" {
+        let input = match args.next() {
+            Some(path) => fs::read_to_string(path),
+            None => load_puzzle_input(8),
+        };
+        let input = input.expect("failed to load displays");
+        let font: Vec<_> = args.collect();
+        let font = match font.split_first() {
+            Some((segments, digits)) => {
+                let segments = segments.parse().expect("failed to parse segment count");
+                let digits: Vec<_> = digits.iter().map(String::as_str).collect();
+                Some((segments, digits))
+            }
+            None => None,
+        };
+        let font = font.as_ref().map(|(s, d)| (*s, d.as_slice()));
+        match solutions::read_displays(&input, font) {
+            Ok(out) => print!("{}", out),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+
+    // `snailfish [trace|sum [tree|flat]|best [k] [tree|flat]|compare] [file]`
+    // adds up snailfish numbers, day 18's homework by default. trace shows
+    // every step, sum uses the given backend (tree by default), best lists the
+    // k pairs with the largest sums (1 by default) and compare times both
+    // backends.
+    if first == "snailfish" {
+        let command = args.next().unwrap_or_else(|| "trace".to_string());
+        let mut rest: Vec<_> = args.collect();
+        let k = match rest.first().map(|k| k.parse::<usize>()) {
+            Some(Ok(k)) if command == "best" => {
+                rest.remove(0);
+                k
+            }
+            _ => 1,
+        };
+        let backend = match rest.first().map(String::as_str) {
+            Some("tree") | Some("flat") => rest.remove(0),
+            _ => "tree".to_string(),
+        };
+        let input = match rest.first() {
+            Some(path) => fs::read_to_string(path),
+            None => load_puzzle_input(18),
+        };
+        let input = input.expect("failed to load homework");
+        let input = input.trim_end();
+        let out = match command.as_str() {
+            "trace" => solutions::snailfish_reductions(input),
+            "sum" => solutions::snailfish_sum(input, &backend),
+            "best" => solutions::best_snailfish_pairs(input, k, &backend),
+            "compare" => solutions::compare_snailfish_backends(input),
+            _ => Err(format!(
+                "unknown command {}, try trace, sum, best or compare",
+                command
+            )),
+        };
+        match out {
+            Ok(report) => print!("{}", report),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+
+    // `scanners [tree|csv|ply|poses [id]]` shows how day 19's scanners line
+    // up, exports them and their beacons for a 3D viewer, or lists their poses
+    // relative to scanner id (0 by default).
+    if first == "scanners" {
+        let format = args.next().unwrap_or_else(|| "tree".to_string());
+        let input = load_puzzle_input(19).expect("failed to load puzzle input");
+        let out = if format == "poses" {
+            let reference = args
+                .next()
+                .map(|id| id.parse().expect("failed to parse scanner id"))
+                .unwrap_or(0);
+            solutions::scanner_poses(&input, reference)
+        } else {
+            solutions::export_scanners(&input, &format)
+        };
+        match out {
+            Ok(out) => print!("{}", out),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+
+    // `trajectory [vx,vy]` plots day 17's probe launched at that velocity, or
+    // draws every launch that hits the target as SVG.
+    if first == "trajectory" {
+        let velocity = args.next().map(|v| {
+            let coords: Vec<i64> = v
+                .split(',')
+                .map(|c| c.parse().expect("failed to parse velocity"))
+                .collect();
+            coords.try_into().expect("a velocity needs two components")
+        });
+        let input = load_puzzle_input(17).expect("failed to load puzzle input");
+        match solutions::trajectory_report(&input, velocity) {
+            Ok(report) => print!("{}", report),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+
+    // `reactor [fragments|signed|compressed] [x,y,z]` replays day 22's reboot
+    // step by step with the given backend (signed by default), and says which
+    // step last switched the point.
+    if first == "reactor" {
+        let mut rest = args.peekable();
+        let backend = match rest.peek().map(String::as_str) {
+            Some("fragments") | Some("signed") | Some("compressed") => rest.next().unwrap(),
+            _ => "signed".to_string(),
+        };
+        let point = rest.next().map(|p| {
+            let coords: Vec<i64> = p
+                .split(',')
+                .map(|c| c.parse().expect("failed to parse point"))
+                .collect();
+            coords.try_into().expect("a point needs three coordinates")
+        });
+        let input = load_puzzle_input(22).expect("failed to load puzzle input");
+        match solutions::reactor_report(&input, point, &backend) {
+            Ok(report) => print!("{}", report),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+
+    // `debug [file]` steps through an ALU program, day 24's input by default.
+    if first == "debug" {
+        let program = match args.next() {
+            Some(path) => fs::read_to_string(path),
+            None => load_puzzle_input(24),
+        };
+        let program = program.expect("failed to load program");
+        solutions::debug_alu(&program).expect("failed to run debugger");
+        return;
+    }
+
+    // `amphipods [2] [stats]` replays the solution to day 23, unfolded for
+    // part 2, or compares how the search does with its shortcuts turned off.
+    if first == "amphipods" {
+        let rest: Vec<_> = args.collect();
+        let unfold = rest.iter().any(|a| a == "2");
+        let input = load_puzzle_input(23).expect("failed to load puzzle input");
+        if rest.iter().any(|a| a == "stats") {
+            match solutions::amphipod_search_stats(&input, unfold) {
+                Ok(stats) => print!("{}", stats),
+                Err(e) => eprintln!("{}", e),
+            }
+        } else {
+            solutions::replay_amphipods(&input, unfold).expect("failed to replay");
+        }
+        return;
+    }
+
+    // `dirac [board,target,sides,rolls]` breaks down the odds of day 21's
+    // Dirac dice game, with part two's rules by default.
+    if first == "dirac" {
+        let rules = args.next().map(|r| {
+            let rules: Vec<u32> = r
+                .split(',')
+                .map(|c| c.parse().expect("failed to parse rules"))
+                .collect();
+            rules.try_into().expect("rules need four numbers")
+        });
+        let input = load_puzzle_input(21).expect("failed to load puzzle input");
+        match solutions::dirac_report(&input, rules) {
+            Ok(report) => print!("{}", report),
+            Err(e) => eprintln!("{}", e),
+        }
+        return;
+    }
+
+    let day: usize = first.parse().expect("failed to parse arg");
     let problem: usize = args
         .next()
         .expect("not enough args")
@@ -1,4 +1,6 @@
+use crate::lib::geom;
 use crate::lib::grid::Point;
+use crate::lib::interval::{IntervalSet, Range};
 
 pub fn problem1(input: &str) -> String {
     let data = parser::parse(input).unwrap().1;
@@ -19,64 +21,63 @@ fn find_overlap<'a, I>(data: I) -> usize
 where
     I: IntoIterator<Item = &'a (Point, Point)>,
 {
-    let mut grid = GrowableGrid::default();
-    data.into_iter().for_each(|&(a, b)| grid.apply_line(a, b));
+    let mut coverage = Coverage::default();
+    data.into_iter()
+        .for_each(|&(a, b)| coverage.apply_line(a, b));
 
-    grid.cells
+    let overlaps: u64 = coverage
+        .rows
         .iter()
-        .flat_map(|r| r.iter())
-        .filter(|&&c| c >= 2)
-        .count()
+        .map(|r| r.twice.measure().unwrap())
+        .sum();
+    overlaps as usize
 }
 
+// The columns covered by at least one and at least two lines, for each row.
 #[derive(Clone, Default)]
-struct GrowableGrid {
-    cells: Vec<Vec<u32>>,
+struct Coverage {
+    rows: Vec<Row>,
 }
 
-impl GrowableGrid {
-    fn get_mut_or_default(&mut self, p: Point) -> &mut u32 {
-        if p.y >= self.cells.len() {
-            self.cells.resize(p.y + 1, Vec::new());
-        }
-
-        let row = &mut self.cells[p.y];
+#[derive(Clone, Default)]
+struct Row {
+    once: IntervalSet<usize>,
+    twice: IntervalSet<usize>,
+}
 
-        if p.x >= row.len() {
-            row.resize(p.x + 1, 0);
+impl Coverage {
+    fn cover(&mut self, y: usize, xs: Range<usize>) {
+        if y >= self.rows.len() {
+            self.rows.resize(y + 1, Row::default());
         }
 
-        &mut row[p.x]
+        let row = &mut self.rows[y];
+        let line: IntervalSet<_> = [xs].into_iter().collect();
+        for r in row.once.intersection(&line).ranges() {
+            row.twice.insert(*r);
+        }
+        row.once.insert(xs);
     }
 
     fn apply_line(&mut self, a: Point, b: Point) {
-        let (dx, dy) = reduce_fraction(b.x as isize - a.x as isize, b.y as isize - a.y as isize);
-
-        let mut p = a;
-        while p != b {
-            *self.get_mut_or_default(p) += 1;
-            p = Point::new(
-                (p.x as isize + dx).try_into().unwrap(),
-                (p.y as isize + dy).try_into().unwrap(),
-            );
+        if a.y == b.y {
+            self.cover(a.y, Range::inclusive(a.x.min(b.x), a.x.max(b.x)));
+            return;
         }
-        *self.get_mut_or_default(p) += 1;
-    }
-}
 
-// Reduces the fraction while keeping all negatives.
-fn reduce_fraction(a: isize, b: isize) -> (isize, isize) {
-    fn gcd(a: usize, b: usize) -> usize {
-        let (a, b) = (a.max(b), a.min(b));
-        if b == 0 {
-            return a;
+        // vertical and diagonal lines cross each row at a single column.
+        let end = geom::Point::from(b);
+        let mut p = geom::Point::from(a);
+        let step = (end - p).reduced();
+        loop {
+            let q: Point = p.try_into().unwrap();
+            self.cover(q.y, Range::inclusive(q.x, q.x));
+            if p == end {
+                break;
+            }
+            p += step;
         }
-
-        gcd(b, a % b)
     }
-
-    let g = gcd(a.abs() as usize, b.abs() as usize);
-    (a / g as isize, b / g as isize)
 }
 
 mod parser {
@@ -119,4 +120,16 @@ mod tests {
     fn problem2_test() {
         assert_eq!(problem2(EXAMPLE_INPUT), "12")
     }
+
+    #[test]
+    fn overlapping_runs_test() {
+        // three lines stacked on one row count each shared point once, and
+        // the diagonal and vertical lines cross it at single points.
+        let input = "0,0 -> 6,0
+4,0 -> 2,0
+3,0 -> 9,0
+8,2 -> 8,0
+0,3 -> 3,0";
+        assert_eq!(problem2(input), "6");
+    }
 }
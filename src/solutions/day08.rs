@@ -15,44 +15,37 @@ pub fn problem2(input: &str) -> String {
     let displays = parser::parse(input).unwrap().1;
     let ans: usize = displays
         .iter()
-        .map(|d| {
-            let t = Translation::compute(&d.seen_patterns);
-            d.final_value(&t)
-        })
+        .map(|d| d.decode(&SEVEN_SEGMENT).unwrap())
         .sum();
 
     format!("{}", ans)
 }
 
-lazy_static! {
-    static ref DIGIT_PATTERN: HashMap<Pattern, usize> = {
-        HashMap::from_iter([
-            (0, Pattern::from_str("abcefg").unwrap()),
-            (1, Pattern::from_str("cf").unwrap()),
-            (2, Pattern::from_str("acdeg").unwrap()),
-            (3, Pattern::from_str("acdfg").unwrap()),
-            (4, Pattern::from_str("bcdf").unwrap()),
-            (5, Pattern::from_str("abdfg").unwrap()),
-            (6, Pattern::from_str("abdefg").unwrap()),
-            (7, Pattern::from_str("acf").unwrap()),
-            (8, Pattern::from_str("abcdefg").unwrap()),
-            (9, Pattern::from_str("abcdfg").unwrap()),
-        ].into_iter().map(|(a, b)| (b, a)))
-    };
+/// Reads every display in input on the given font, or on the usual seven
+/// segments, one line per display.
+pub fn read_displays(input: &str, font: Option<&Font>) -> Result<String, String> {
+    let displays = parser::parse(input).map_err(|e| e.to_string())?.1;
+    let font = font.unwrap_or(&SEVEN_SEGMENT);
 
-    static ref SEGMENT_FINGERPRINT: HashMap<Vec<usize>, Segment> = {
-        let mut fps: HashMap<Segment, Vec<usize>> = HashMap::new();
-        for (pattern, _) in DIGIT_PATTERN.iter() {
-            for &s in &pattern.segments {
-                fps.entry(s).or_default().push(pattern.segments.len());
-            }
+    let mut out = String::new();
+    for (i, d) in displays.iter().enumerate() {
+        match d.decode(font) {
+            Ok(value) => out += &format!("{}: {}\n", i + 1, value),
+            Err(e) => out += &format!("{}: {}\n", i + 1, e),
         }
+    }
+    Ok(out)
+}
 
-        // fingerprints must be sorted.
-        fps.values_mut().for_each(|x| x.sort());
-
-        HashMap::from_iter(fps.into_iter().map(|(s, f)| (f, s)))
-    };
+lazy_static! {
+    static ref SEVEN_SEGMENT: Font = Font::new(
+        7,
+        &[
+            "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg",
+            "abcdfg",
+        ]
+    )
+    .unwrap();
 }
 
 type Segment = char;
@@ -62,6 +55,15 @@ struct Pattern {
     segments: Vec<Segment>,
 }
 
+impl Pattern {
+    // one bit per segment, 'a' first.
+    fn mask(&self) -> u32 {
+        self.segments
+            .iter()
+            .fold(0, |m, &s| m | 1 << (s as u32 - 'a' as u32))
+    }
+}
+
 impl From<Vec<Segment>> for Pattern {
     fn from(mut s: Vec<Segment>) -> Self {
         s.sort();
@@ -77,27 +79,96 @@ impl FromStr for Pattern {
     }
 }
 
+/// The segments lit for each digit, in order, on a display with segments
+/// named from 'a'. Numbers are read in base however many digits there are.
+pub struct Font {
+    segments: usize,
+    digits: Vec<Pattern>,
+}
+
+impl Font {
+    pub fn new(segments: usize, digits: &[&str]) -> Result<Font, &'static str> {
+        if segments > 26 {
+            return Err("fonts have at most 26 segments");
+        }
+        if digits.len() < 2 {
+            return Err("a font needs at least two digits");
+        }
+
+        let last = (b'a' + segments as u8) as char;
+        let digits: Vec<_> = digits
+            .iter()
+            .map(|d| Pattern::from_str(d).unwrap())
+            .collect();
+        if digits
+            .iter()
+            .flat_map(|d| &d.segments)
+            .any(|&s| !('a'..last).contains(&s))
+        {
+            return Err("segment outside the font");
+        }
+        if digits
+            .iter()
+            .enumerate()
+            .any(|(i, d)| digits[..i].contains(d))
+        {
+            return Err("digits must look different");
+        }
+
+        Ok(Font { segments, digits })
+    }
+
+    fn digit(&self, p: &Pattern) -> Option<usize> {
+        self.digits.iter().position(|d| d == p)
+    }
+}
+
 struct Translation {
     m: HashMap<Segment, Segment>,
 }
 
 impl Translation {
-    fn compute(seen_patterns: &[Pattern]) -> Self {
-        let mut fingerprints: HashMap<Segment, Vec<usize>> = HashMap::new();
-        for p in seen_patterns {
-            for s in &p.segments {
-                fingerprints.entry(*s).or_default().push(p.segments.len())
-            }
+    /// Tries wirings that turn every pattern into a digit of the font, one for
+    /// each way of reading the patterns, until visit returns false.
+    fn search(
+        font: &Font,
+        patterns: &[&Pattern],
+        mut visit: impl FnMut(Translation) -> bool,
+    ) -> Result<(), &'static str> {
+        let all = (1 << font.segments) - 1;
+        let mut masks: Vec<u32> = patterns.iter().map(|p| p.mask()).collect();
+        if masks.iter().any(|m| m & !all != 0) {
+            return Err("inconsistent display: a wire outside the font");
         }
 
-        fingerprints.values_mut().for_each(|x| x.sort());
-
-        let m = fingerprints
-            .into_iter()
-            .map(|(s, fp)| (s, *SEGMENT_FINGERPRINT.get(&fp).unwrap()));
+        // the patterns with the fewest digits they could be go first.
+        let digits: Vec<_> = font.digits.iter().map(|d| d.mask()).collect();
+        let candidates = |m: &u32| {
+            digits
+                .iter()
+                .filter(|d| d.count_ones() == m.count_ones())
+                .count()
+        };
+        masks.sort();
+        masks.dedup();
+        masks.sort_by_key(candidates);
 
-        Translation {
-            m: HashMap::from_iter(m),
+        let mut fits = false;
+        let letter = |i: usize| (b'a' + i as u8) as char;
+        solve(&digits, &masks, vec![all; font.segments], &mut |wiring| {
+            fits = true;
+            let m = wiring
+                .iter()
+                .enumerate()
+                .map(|(w, &s)| (letter(w), letter(s)));
+            visit(Translation {
+                m: HashMap::from_iter(m),
+            })
+        });
+        if fits {
+            Ok(())
+        } else {
+            Err("inconsistent display: no wiring fits the patterns")
         }
     }
 
@@ -111,6 +182,54 @@ impl Translation {
     }
 }
 
+// Tries each pattern as each digit with as many segments, narrowing down the
+// segments every wire could drive, then wires them up one to one. Hands one
+// wiring per way of reading the patterns to visit, and returns false once
+// visit has seen enough.
+fn solve(
+    digits: &[u32],
+    patterns: &[u32],
+    wires: Vec<u32>,
+    visit: &mut dyn FnMut(&[usize]) -> bool,
+) -> bool {
+    let Some((&p, rest)) = patterns.split_first() else {
+        let mut wiring = vec![];
+        return !connect(&wires, 0, &mut wiring) || visit(&wiring);
+    };
+
+    for &d in digits.iter().filter(|d| d.count_ones() == p.count_ones()) {
+        // wires in the pattern light the digit's segments, the rest don't.
+        let next: Vec<_> = wires
+            .iter()
+            .enumerate()
+            .map(|(w, &s)| if p & 1 << w != 0 { s & d } else { s & !d })
+            .collect();
+        if !next.contains(&0) && !solve(digits, rest, next, visit) {
+            return false;
+        }
+    }
+    true
+}
+
+// Finds a wiring that gives every wire its own segment, if there is one.
+fn connect(wires: &[u32], used: u32, wiring: &mut Vec<usize>) -> bool {
+    let Some(&segments) = wires.get(wiring.len()) else {
+        return true;
+    };
+
+    let mut free = segments & !used;
+    while free != 0 {
+        let s = free.trailing_zeros();
+        wiring.push(s as usize);
+        if connect(wires, used | 1 << s, wiring) {
+            return true;
+        }
+        wiring.pop();
+        free &= free - 1;
+    }
+    false
+}
+
 pub struct Display {
     seen_patterns: Vec<Pattern>,
     final_patterns: Vec<Pattern>,
@@ -124,12 +243,37 @@ impl Display {
         }
     }
 
-    fn final_value(&self, t: &Translation) -> usize {
+    /// Works out the wirings that fit every pattern on the display, then reads
+    /// the final value off them. It's ambiguous only if they read differently.
+    fn decode(&self, font: &Font) -> Result<usize, &'static str> {
+        let patterns: Vec<_> = self
+            .seen_patterns
+            .iter()
+            .chain(&self.final_patterns)
+            .collect();
+
+        let mut readings = vec![];
+        Translation::search(font, &patterns, |t| {
+            let reading = self.final_value(font, &t);
+            if !readings.contains(&reading) {
+                readings.push(reading);
+            }
+            readings.len() < 2
+        })?;
+        match readings[..] {
+            [Some(value)] => Ok(value),
+            [None] => Err("final value too large to read"),
+            _ => Err("ambiguous display: the wirings that fit read differently"),
+        }
+    }
+
+    fn final_value(&self, font: &Font, t: &Translation) -> Option<usize> {
         self.final_patterns
             .iter()
-            .map(|p| t.translate(p))
-            .map(|p| DIGIT_PATTERN.get(&p).unwrap())
-            .fold(0, |acc, d| acc * 10 + d)
+            .map(|p| font.digit(&t.translate(p)).unwrap())
+            .try_fold(0usize, |acc, d| {
+                acc.checked_mul(font.digits.len())?.checked_add(d)
+            })
     }
 }
 
@@ -139,7 +283,7 @@ mod parser {
 
     pub fn parse(input: &str) -> IResult<&str, Vec<Display>> {
         let patterns = || {
-            let segment = one_of("abcdefg");
+            let segment = verify(anychar, char::is_ascii_lowercase);
             let pattern = map(many1(segment), |x| Pattern::from(x));
             separated_list1(space1, pattern)
         };
@@ -176,4 +320,55 @@ gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce
     fn problem2_test() {
         assert_eq!(problem2(EXAMPLE_INPUT), "61229")
     }
+
+    #[test]
+    fn decode_test() {
+        let display = |line| parser::parse(line).unwrap().1.remove(0);
+        let d = display(
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+        );
+        assert_eq!(d.decode(&SEVEN_SEGMENT), Ok(5353));
+
+        // two different patterns can't both be a 1.
+        let d = display("ab cd | ab");
+        assert!(d
+            .decode(&SEVEN_SEGMENT)
+            .unwrap_err()
+            .starts_with("inconsistent"));
+        // a 1 alone doesn't say which wire is which, but it still reads 1.
+        let d = display("ab | ab");
+        assert_eq!(d.decode(&SEVEN_SEGMENT), Ok(1));
+        // five segments could be a 2, 3 or 5.
+        let d = display("abcde | abcde");
+        assert!(d
+            .decode(&SEVEN_SEGMENT)
+            .unwrap_err()
+            .starts_with("ambiguous"));
+
+        // base 4 on three segments, wired a -> b, b -> c, c -> a.
+        let font = Font::new(3, &["a", "ab", "abc", "bc"]).unwrap();
+        let d = display("c ac abc ab | c ab ac");
+        assert_eq!(d.decode(&font), Ok(3 * 4 + 1));
+        let d = display("abc | abc");
+        assert_eq!(d.decode(&font), Ok(2));
+        let d = display("ab | ab");
+        assert!(d.decode(&font).unwrap_err().starts_with("ambiguous"));
+        let d = display("d | d");
+        assert!(d.decode(&font).unwrap_err().starts_with("inconsistent"));
+
+        assert_eq!(
+            read_displays("c ac abc ab | c ab ac\nab | ab", Some(&font)),
+            Ok("1: 13\n2: ambiguous display: the wirings that fit read differently\n".to_string())
+        );
+        assert_eq!(read_displays("ab | ab", None), Ok("1: 1\n".to_string()));
+
+        assert_eq!(
+            Font::new(2, &["a", "c"]).err(),
+            Some("segment outside the font")
+        );
+        assert_eq!(
+            Font::new(2, &["ab", "ba"]).err(),
+            Some("digits must look different")
+        );
+    }
 }
@@ -1,144 +1,348 @@
+use crate::lib::geom::{BoundingBox, Point, Vector};
+use crate::lib::interval::{IntervalSet, Range};
+use std::fmt::Write;
+
 pub fn problem1(input: &str) -> String {
-    let (_x_target, y_target) = parser::parse(input).unwrap().1;
-    // Wrong, but it solves the problem...
-    // TODO: replace with values obtained from problem2
-    let ans: i32 = (1..y_target.start.abs()).sum();
+    let target = parser::parse(input).unwrap().1;
+    let shots = solve(&target).expect("infinitely many velocities hit the target");
+    let ans = shots.iter().map(|s| s.max_height()).max().unwrap();
 
     format!("{}", ans)
 }
 
 pub fn problem2(input: &str) -> String {
-    let (x_target, y_target) = parser::parse(input).unwrap().1;
-    let x_ranges: Vec<_> = (0..x_target.end.unwrap() + 1)
-        .filter_map(|v| Some((v, time_on_target_x(v, &x_target)?)))
-        .collect();
-    let y_ranges: Vec<_> = (y_target.start - 1..y_target.start.abs() + 1)
-        .filter_map(|v| Some((v, time_on_target_y(v, &y_target)?)))
+    let target = parser::parse(input).unwrap().1;
+    let shots = solve(&target).expect("infinitely many velocities hit the target");
+
+    format!("{}", shots.len())
+}
+
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub x: Range<i64>,
+    pub y: Range<i64>,
+}
+
+impl Target {
+    pub fn contains(&self, p: Point<2>) -> bool {
+        self.x.contains(p[0]) && self.y.contains(p[1])
+    }
+
+    fn corners(&self) -> [Point<2>; 2] {
+        let last = |r: &Range<i64>| r.last().expect("target must be bounded");
+        [
+            Point([self.x.start, self.y.start]),
+            Point([last(&self.x), last(&self.y)]),
+        ]
+    }
+}
+
+/// A launch velocity that hits the target, and the steps at which the probe is
+/// inside it.
+#[derive(Clone, Debug)]
+pub struct Shot {
+    pub velocity: Vector<2>,
+    pub steps: IntervalSet<i64>,
+}
+
+impl Shot {
+    pub fn max_height(&self) -> i64 {
+        max_height(self.velocity[1])
+    }
+
+    /// Positions from the launch up to the last step on target.
+    pub fn trajectory(&self) -> Vec<Point<2>> {
+        let last = self.steps.ranges().last().and_then(|r| r.last());
+        let last = last.expect("shots on an unbounded step range never end");
+        trajectory(self.velocity).take(last as usize + 1).collect()
+    }
+}
+
+/// Every velocity that puts the probe inside the target after some step, or
+/// None if there are infinitely many.
+pub fn solve(target: &Target) -> Option<Vec<Shot>> {
+    // an unbounded target can always be hit by throwing harder.
+    let x_last = target.x.last()?;
+    let y_last = target.y.last()?;
+
+    // anything faster overshoots on the first step.
+    let x_velocities = target.x.start.min(0)..=x_last.max(0);
+    let x_hits: Vec<_> = x_velocities
+        .map(|vx| (vx, x_steps(vx, &target.x)))
+        .filter(|(_, s)| !s.is_empty())
         .collect();
 
-    let mut count = 0;
+    // a probe thrown upwards comes back down through y=0 at step 2vy+1 and
+    // moving at -(vy+1). If the target row holds y=0 that is always a hit, so
+    // only the x steps limit how high it can be thrown.
+    let mut max_vy = y_last.max(-target.y.start - 1).max(0);
+    if target.y.contains(0) {
+        let x_steps_end: Option<Vec<i64>> = x_hits
+            .iter()
+            .map(|(_, s)| s.ranges().last().unwrap().end)
+            .collect();
+        max_vy = max_vy.max(x_steps_end?.into_iter().max().unwrap_or(0));
+    }
+    let y_velocities = target.y.start.min(0)..=max_vy;
 
-    for (_y_v, y_range) in y_ranges.iter() {
-        for (_x_v, x_range) in x_ranges.iter() {
-            if x_range.overlap(y_range) {
-                count += 1;
+    let mut ret = Vec::new();
+    for vy in y_velocities {
+        let y_hits = y_steps(vy, &target.y);
+        for (vx, x_hits) in x_hits.iter() {
+            let steps = x_hits.intersection(&y_hits);
+            if !steps.is_empty() {
+                ret.push(Shot {
+                    velocity: Vector([*vx, vy]),
+                    steps,
+                });
             }
         }
     }
 
-    format!("{}", count)
+    Some(ret)
 }
 
-fn time_on_target_x(initial_velocity: i32, target: &Range) -> Option<Range> {
-    let end = target
-        .end
-        .expect("time_on_target_x: target must be bounded");
+/// Plots a launch at velocity and the steps it spends on target, or draws every
+/// shot that hits as SVG if there's no velocity.
+pub fn trajectory_report(input: &str, velocity: Option<[i64; 2]>) -> Result<String, String> {
+    let target = parser::parse(input).map_err(|e| e.to_string())?.1;
 
-    let mut v = initial_velocity;
-    let mut cur = 0;
-    let mut steps = 0;
-    let mut first_step_on_target = None;
+    let Some(velocity) = velocity else {
+        let shots = solve(&target).ok_or("infinitely many velocities hit the target")?;
+        return Ok(to_svg(&target, &shots));
+    };
 
-    loop {
-        if target.contains(cur) {
-            first_step_on_target.get_or_insert(steps);
-        }
+    let velocity = Vector(velocity);
+    let x_hits = x_steps(velocity[0], &target.x);
+    let y_hits = y_steps(velocity[1], &target.y);
+    let steps = x_hits.intersection(&y_hits);
+    let mut out = plot(&target, velocity);
+    match steps.measure() {
+        _ if steps.is_empty() => out += "misses the target\n",
+        Some(n) => out += &format!("on target at steps {:?}, {} in all\n", steps.ranges(), n),
+        None => out += &format!("on target at steps {:?}, never leaving\n", steps.ranges()),
+    }
 
-        if cur > end {
-            return first_step_on_target.map(|s| Range::new(s, steps - 1));
-        }
+    // lined up with the target on one axis but not the other.
+    let above_or_below = x_hits.difference(&y_hits);
+    if !above_or_below.is_empty() {
+        out += &format!("above or below it at steps {:?}\n", above_or_below.ranges());
+    }
+    let beside = y_hits.difference(&x_hits);
+    if !beside.is_empty() {
+        out += &format!("beside it at steps {:?}\n", beside.ranges());
+    }
+    Ok(out)
+}
 
-        if v == 0 {
-            return first_step_on_target.map(|s| Range::new_unbounded(s));
-        }
+/// Where a probe launched at velocity is after t steps.
+pub fn position(velocity: Vector<2>, t: i64) -> Point<2> {
+    Point([x_position(velocity[0], t), y_position(velocity[1], t)])
+}
 
-        steps += 1;
-        cur += v;
-        v = (v - 1).max(0);
-    }
+/// Positions of a probe launched at velocity, starting at the launcher.
+pub fn trajectory(velocity: Vector<2>) -> impl Iterator<Item = Point<2>> {
+    (0..).map(move |t| position(velocity, t))
 }
 
-fn time_on_target_y(initial_velocity: i32, target: &Range) -> Option<Range> {
-    let start = target.start;
+fn max_height(vy: i64) -> i64 {
+    if vy > 0 {
+        vy * (vy + 1) / 2
+    } else {
+        0
+    }
+}
 
-    let mut v = initial_velocity;
-    let mut cur = 0;
-    let mut steps = 0;
-    let mut first_step_on_target = None;
+// Drag slows the probe by one each step until it stops after |vx| steps.
+fn x_position(vx: i64, t: i64) -> i64 {
+    let t = t.min(vx.abs());
+    let dist = t * vx.abs() - t * (t - 1) / 2;
+    dist * vx.signum()
+}
 
-    loop {
-        if target.contains(cur) {
-            first_step_on_target.get_or_insert(steps);
-        }
+// Symmetric around the peak: y(t) == y(2vy + 1 - t).
+fn y_position(vy: i64, t: i64) -> i64 {
+    t * vy - t * (t - 1) / 2
+}
 
-        if cur < start {
-            return first_step_on_target.map(|s| Range::new(s, steps - 1));
-        }
+// Steps at which the probe is inside the target columns.
+fn x_steps(vx: i64, target: &Range<i64>) -> IntervalSet<i64> {
+    let stop = vx.abs();
+    let pos = |t| x_position(vx, t);
 
-        steps += 1;
-        cur += v;
-        v = v - 1;
+    let mut ret = IntervalSet::new();
+    ret.insert(monotone_hits(1, stop + 1, vx > 0, pos, target));
+    if target.contains(pos(stop)) {
+        ret.insert(Range::unbounded(stop.max(1)));
     }
+    ret
 }
 
-// A bounded [start, end] or unbounded [start, inf) range.
-#[derive(Clone, Debug)]
-pub struct Range {
-    start: i32,
-    end: Option<i32>,
+// Steps at which the probe is inside the target rows: at most one stretch on
+// the way up and one on the way down.
+fn y_steps(vy: i64, target: &Range<i64>) -> IntervalSet<i64> {
+    let pos = |t| y_position(vy, t);
+    let peak = vy.max(0);
+
+    // falling at least one unit per step after the first, so this is past the
+    // bottom of the target.
+    let descent = peak + 1;
+    let past = descent + (pos(descent) - target.start).max(0) + 2;
+
+    let mut ret = IntervalSet::new();
+    ret.insert(monotone_hits(1, peak + 1, true, pos, target));
+    ret.insert(monotone_hits(descent, past, false, pos, target));
+    ret
 }
 
-impl Range {
-    fn new(start: i32, end: i32) -> Self {
-        Range {
-            start: start,
-            end: Some(end),
+// The steps in [from, to) at which pos is inside target, where pos only ever
+// moves one way over that stretch.
+fn monotone_hits(
+    from: i64,
+    to: i64,
+    increasing: bool,
+    pos: impl Fn(i64) -> i64,
+    target: &Range<i64>,
+) -> Range<i64> {
+    let below = |t| pos(t) < target.start;
+    let above = |t| target.end.is_some_and(|end| pos(t) >= end);
+
+    let (first, last) = if increasing {
+        let first = partition_point(from, to, below);
+        (first, partition_point(first, to, |t| !above(t)))
+    } else {
+        let first = partition_point(from, to, above);
+        (first, partition_point(first, to, |t| !below(t)))
+    };
+
+    Range::half_open(first, last)
+}
+
+// The first t in [from, to) for which pred is false, given pred is true up to
+// some point and false after.
+fn partition_point(mut from: i64, mut to: i64, pred: impl Fn(i64) -> bool) -> i64 {
+    while from < to {
+        let mid = from + (to - from) / 2;
+        if pred(mid) {
+            from = mid + 1;
+        } else {
+            to = mid;
         }
     }
+    from
+}
+
+/// Draws a launch the way the puzzle does: S at the launcher, # at each
+/// position and T over the target, with y increasing upwards. The path stops
+/// at the first step on target or once the probe can no longer reach it.
+pub fn plot(target: &Target, velocity: Vector<2>) -> String {
+    let path = launch_path(target, velocity);
+    let points = path.iter().copied().chain(target.corners());
+    let bb = BoundingBox::from_points(points.chain([Point::ORIGIN])).unwrap();
 
-    fn new_unbounded(start: i32) -> Self {
-        Range {
-            start: start,
-            end: None,
+    let mut out = String::new();
+    for y in (bb.min[1]..=bb.max[1]).rev() {
+        for x in bb.min[0]..=bb.max[0] {
+            let p = Point([x, y]);
+            let c = if p == Point::ORIGIN {
+                'S'
+            } else if path.contains(&p) {
+                '#'
+            } else if target.contains(p) {
+                'T'
+            } else {
+                '.'
+            };
+            out.push(c);
         }
+        out.push('\n');
     }
+    out
+}
 
-    fn contains(&self, n: i32) -> bool {
-        if let Some(end) = self.end {
-            if n > end {
-                return false;
-            }
-        }
+/// Every shot's trajectory up to its last step on target as an SVG polyline,
+/// over the target area. y increases upwards as in the puzzle.
+pub fn to_svg(target: &Target, shots: &[Shot]) -> String {
+    let paths: Vec<_> = shots.iter().map(|s| s.trajectory()).collect();
+    let [low, high] = target.corners();
+    let points = paths.iter().flatten().copied().chain([low, high]);
+    let bb = BoundingBox::from_points(points).unwrap();
+    let size = bb.size();
 
-        n >= self.start
+    // svg's y axis points down, so every y is flipped.
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="{} {} {} {}">"#,
+        bb.min[0], -bb.max[1], size[0], size[1]
+    )
+    .unwrap();
+    writeln!(
+        out,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="lightgreen"/>"#,
+        low[0],
+        -high[1],
+        high[0] - low[0] + 1,
+        high[1] - low[1] + 1
+    )
+    .unwrap();
+    for path in paths.iter() {
+        let coords: Vec<_> = path.iter().map(|p| format!("{},{}", p[0], -p[1])).collect();
+        writeln!(
+            out,
+            r#"<polyline points="{}" fill="none" stroke="steelblue" vector-effect="non-scaling-stroke"/>"#,
+            coords.join(" ")
+        )
+        .unwrap();
     }
+    writeln!(out, "</svg>").unwrap();
+    out
+}
 
-    fn overlap(&self, other: &Range) -> bool {
-        if self.start > other.end.unwrap_or(i32::MAX) || other.start > self.end.unwrap_or(i32::MAX)
-        {
-            false
-        } else {
-            true
+// Positions after each step until the probe is on target or has missed it.
+fn launch_path(target: &Target, velocity: Vector<2>) -> Vec<Point<2>> {
+    let mut ret = Vec::new();
+    for t in 1.. {
+        let p = position(velocity, t);
+        ret.push(p);
+        if target.contains(p) || missed(target, velocity, t) {
+            break;
         }
     }
+    ret
 }
 
-impl From<(i32, i32)> for Range {
-    fn from(r: (i32, i32)) -> Self {
-        Range::new(r.0, r.1)
-    }
+// Whether the probe can no longer reach the target after t steps: it is
+// below it and not rising, or beside it and not heading back.
+fn missed(target: &Target, velocity: Vector<2>, t: i64) -> bool {
+    let p = position(velocity, t);
+    let vx = velocity[0].signum() * (velocity[0].abs() - t).max(0);
+    let vy = velocity[1] - t;
+
+    let below = p[1] < target.y.start && vy <= 0;
+    let left = p[0] < target.x.start && vx <= 0;
+    let right = target.x.end.is_some_and(|end| p[0] >= end) && vx >= 0;
+    below || left || right
 }
 
 mod parser {
-    use super::Range;
+    use super::Target;
     use crate::lib::combinators::*;
+    use crate::lib::interval::Range;
 
-    pub fn parse(input: &str) -> IResult<&str, (Range, Range)> {
-        let range = || map(separated_pair(int, tag(".."), int), |r| r.into());
-        let parser = separated_pair(
-            preceded(tag("x="), range()),
-            tag(", "),
-            preceded(tag("y="), range()),
+    pub fn parse(input: &str) -> IResult<&str, Target> {
+        let range = || {
+            map(separated_pair(int, tag(".."), int), |(s, e)| {
+                Range::inclusive(s, e)
+            })
+        };
+        let parser = map(
+            separated_pair(
+                preceded(tag("x="), range()),
+                tag(", "),
+                preceded(tag("y="), range()),
+            ),
+            |(x, y)| Target { x, y },
         );
         complete(preceded(tag("target area: "), parser))(input)
     }
@@ -147,6 +351,7 @@ mod parser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::BTreeMap;
 
     const EXAMPLE_INPUT: &str = "target area: x=20..30, y=-10..-5";
 
@@ -158,4 +363,139 @@ mod tests {
     fn problem2_test() {
         assert_eq!(problem2(EXAMPLE_INPUT), "112")
     }
+
+    // Steps each velocity spends on target, found by stepping the probe.
+    fn simulate(target: &Target, max_v: i64, max_t: i64) -> BTreeMap<[i64; 2], Vec<i64>> {
+        let mut ret = BTreeMap::new();
+        for vx in -max_v..=max_v {
+            for vy in -max_v..=max_v {
+                let (mut p, mut v) = (Point([0, 0]), Vector([vx, vy]));
+                let mut steps = Vec::new();
+                for t in 1..=max_t {
+                    p += v;
+                    v = Vector([v[0] - v[0].signum(), v[1] - 1]);
+                    if target.contains(p) {
+                        steps.push(t);
+                    }
+                }
+                if !steps.is_empty() {
+                    ret.insert([vx, vy], steps);
+                }
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn any_quadrant_test() {
+        let targets = [
+            "target area: x=20..30, y=-10..-5",
+            "target area: x=-30..-20, y=-10..-5",
+            "target area: x=-8..-3, y=5..9",
+            "target area: x=4..9, y=3..12",
+            "target area: x=-4..6, y=-7..-2",
+            "target area: x=12..13, y=-3..2",
+        ];
+
+        for input in targets {
+            let target = parser::parse(input).unwrap().1;
+            let shots = solve(&target).unwrap();
+            let expected = simulate(&target, 40, 120);
+
+            let found: BTreeMap<_, _> = shots
+                .iter()
+                .map(|s| {
+                    let steps = s
+                        .steps
+                        .ranges()
+                        .iter()
+                        .flat_map(|r| r.start..r.end.unwrap());
+                    (s.velocity.0, steps.collect::<Vec<_>>())
+                })
+                .collect();
+            assert_eq!(found, expected, "{}", input);
+        }
+
+        let mirrored = "target area: x=-30..-20, y=-10..-5";
+        assert_eq!(problem1(mirrored), "45");
+        assert_eq!(problem2(mirrored), "112");
+    }
+
+    #[test]
+    fn infinite_test() {
+        let mut target = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        target.x = Range::unbounded(20);
+        assert!(solve(&target).is_none());
+
+        // x=6 stops inside the target on a row that holds y=0.
+        let target = parser::parse("target area: x=20..30, y=-3..2").unwrap().1;
+        assert!(solve(&target).is_none());
+    }
+
+    #[test]
+    fn trajectory_test() {
+        let target = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let shots = solve(&target).unwrap();
+        let shot = |v: [i64; 2]| shots.iter().find(|s| s.velocity.0 == v);
+
+        let s = shot([6, 9]).unwrap();
+        assert_eq!(s.max_height(), 45);
+
+        let s = shot([9, 0]).unwrap();
+        assert_eq!(s.steps.ranges(), &[Range::inclusive(4, 4)]);
+        assert_eq!(
+            s.trajectory(),
+            vec![
+                Point([0, 0]),
+                Point([9, 0]),
+                Point([17, -1]),
+                Point([24, -3]),
+                Point([30, -6]),
+            ]
+        );
+
+        assert!(shot([17, -4]).is_none());
+        assert_eq!(position(Vector([7, 2]), 7), Point([28, -7]));
+    }
+
+    #[test]
+    fn plot_test() {
+        let target = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let expected = ".............#....#............
+.......#..............#........
+...............................
+S........................#.....
+...............................
+...............................
+...........................#...
+...............................
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+....................TTTTTTTT#TT
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+....................TTTTTTTTTTT
+";
+        assert_eq!(plot(&target, Vector([7, 2])), expected);
+
+        // misses off to the right, so the path stops once it is past.
+        let plot = plot(&target, Vector([17, -4]));
+        assert_eq!(plot.lines().next().unwrap().len(), 34);
+        assert_eq!(plot.matches('#').count(), 2);
+
+        let shots = solve(&target).unwrap();
+        let svg = to_svg(&target, &shots);
+        assert!(svg.starts_with("<svg "));
+        assert_eq!(svg.matches("<polyline").count(), 112);
+        assert_eq!(trajectory_report(EXAMPLE_INPUT, None), Ok(svg));
+
+        let report = trajectory_report(EXAMPLE_INPUT, Some([6, 9])).unwrap();
+        assert!(report.ends_with(
+            "on target at steps [[20..21)], 1 in all
+above or below it at steps [[5..20), [21..)]\n"
+        ));
+        let report = trajectory_report(EXAMPLE_INPUT, Some([17, -4])).unwrap();
+        assert!(report.ends_with("misses the target\nbeside it at steps [[2..3)]\n"));
+        assert!(trajectory_report("target area: x=20..30", None).is_err());
+    }
 }
@@ -1,36 +1,408 @@
+use crate::lib::combinators::{complete, IResult};
+use flat::FlatSnailfishNum;
+use nom::Finish;
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fmt;
 use std::fmt::Write;
-use tree_walker::TreeWalker;
+use std::iter;
+use std::ops;
+use std::str::FromStr;
+use std::time::Instant;
+use tree_walker::{Direction, TreeWalker};
 
 pub fn problem1(input: &str) -> String {
-    format!("{}", add_nums(input).magnitude())
+    let nums = parser::parse::<SnailfishNum>(input).unwrap().1;
+    let sum: Option<SnailfishNum> = nums.into_iter().sum();
+    format!("{}", sum.unwrap().magnitude())
 }
 
 pub fn problem2(input: &str) -> String {
-    let nums = parser::parse(input).unwrap().1;
-    let ans = (0..nums.len())
-        .flat_map(|i| (0..nums.len()).map(move |j| (i, j)))
-        .filter(|(i, j)| i != j)
+    let nums = parser::parse::<SnailfishNum>(input).unwrap().1;
+    format!("{}", best_pairs(&nums, 1)[0].magnitude)
+}
+
+/// An ordered pair of homework numbers and the magnitude of their sum.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PairMagnitude {
+    pub left_index: usize,
+    pub right_index: usize,
+    pub left: String,
+    pub right: String,
+    pub magnitude: u64,
+}
+
+/// Returns the k ordered pairs of distinct numbers whose sums have the largest
+/// magnitudes, best first. Ties are broken by the pair's indices.
+fn best_pairs<T: Snailfish + Send + Sync>(nums: &[T], k: usize) -> Vec<PairMagnitude> {
+    let n = nums.len();
+    let best = (0..n)
+        .into_par_iter()
+        .flat_map_iter(|i| (0..n).filter(move |&j| j != i).map(move |j| (i, j)))
         .map(|(i, j)| {
             let mut a = nums[i].clone();
             a.add(&nums[j]);
-            a.magnitude()
+            (a.magnitude(), Reverse(i), Reverse(j))
         })
-        .max()
+        .fold(BinaryHeap::new, |mut best, score| {
+            keep_best(&mut best, score, k);
+            best
+        })
+        .reduce(BinaryHeap::new, |mut best, other| {
+            for Reverse(score) in other {
+                keep_best(&mut best, score, k);
+            }
+            best
+        });
+
+    let mut best: Vec<_> = best.into_iter().map(|Reverse(score)| score).collect();
+    best.sort_unstable_by(|a, b| b.cmp(a));
+    best.into_iter()
+        .map(|(magnitude, Reverse(i), Reverse(j))| PairMagnitude {
+            left_index: i,
+            right_index: j,
+            left: nums[i].to_string(),
+            right: nums[j].to_string(),
+            magnitude,
+        })
+        .collect()
+}
+
+/// Lists the k pairs of homework numbers whose sums have the largest
+/// magnitudes, best first, found with the given backend. Numbers are indexed
+/// from 0 in the order they're given.
+pub fn best_pairs_report(input: &str, k: usize, backend: Backend) -> Result<String, String> {
+    fn parse<T: Snailfish>(input: &str) -> Result<Vec<T>, String> {
+        Ok(parser::parse::<T>(input).map_err(|e| e.to_string())?.1)
+    }
+
+    let best = match backend {
+        Backend::Tree => best_pairs(&parse::<SnailfishNum>(input)?, k),
+        Backend::Flat => best_pairs(&parse::<FlatSnailfishNum>(input)?, k),
+    };
+
+    let mut out = String::new();
+    for (rank, p) in best.iter().enumerate() {
+        writeln!(
+            out,
+            "{}. numbers {} + {}, magnitude {}",
+            rank + 1,
+            p.left_index,
+            p.right_index,
+            p.magnitude
+        )
         .unwrap();
-    format!("{}", ans)
+        writeln!(out, "     {}\n   + {}", p.left, p.right).unwrap();
+    }
+    Ok(out)
+}
+
+// A pair's magnitude, then its indices so that earlier pairs win ties.
+type PairScore = (u64, Reverse<usize>, Reverse<usize>);
+
+// Adds score to a min-heap holding the k best scores seen so far.
+fn keep_best(best: &mut BinaryHeap<Reverse<PairScore>>, score: PairScore, k: usize) {
+    best.push(Reverse(score));
+    if best.len() > k {
+        best.pop();
+    }
+}
+
+/// Adds up the homework a number at a time, listing every explode and split
+/// it takes. Paths go from the root, L and R for each pair on the way.
+pub fn reduction_report(input: &str) -> Result<String, String> {
+    let nums = parser::parse::<SnailfishNum>(input)
+        .map_err(|e| e.to_string())?
+        .1;
+    let mut nums = nums.into_iter();
+    let mut sum = nums.next().ok_or("no numbers to add")?;
+
+    let mut out = String::new();
+    for num in nums {
+        writeln!(out, "  {}\n+ {}", sum, num).unwrap();
+        sum.join(&num);
+        for (action, reduced) in sum.reduce_trace() {
+            let (name, path) = match action {
+                ReduceAction::Explode(path) => ("explode", path),
+                ReduceAction::Split(path) => ("split", path),
+            };
+            let path: String = path
+                .iter()
+                .map(|d| match d {
+                    Direction::Left => 'L',
+                    Direction::Right => 'R',
+                })
+                .collect();
+            writeln!(out, "{:<7} {:<4}  {}", name, path, reduced).unwrap();
+            sum = reduced;
+        }
+        writeln!(out, "= {}\n", sum).unwrap();
+    }
+
+    writeln!(out, "magnitude {}", sum.magnitude()).unwrap();
+    Ok(out)
+}
+
+/// Which representation of snailfish numbers to compute with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// Pairs in an arena, walked as a tree.
+    Tree,
+    /// Literals in order, each tagged with its depth.
+    Flat,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tree" => Ok(Backend::Tree),
+            "flat" => Ok(Backend::Flat),
+            _ => Err(format!("unknown backend {}, try tree or flat", s)),
+        }
+    }
 }
 
-fn add_nums(input: &str) -> SnailfishNum {
-    let nums = parser::parse(input).unwrap().1;
-    let ans = nums
-        .into_iter()
+/// Adds up the homework with the given backend, reporting the magnitude of the
+/// sum and how long it took.
+pub fn sum_report(input: &str, backend: Backend) -> Result<String, String> {
+    let start = Instant::now();
+    let magnitude = match backend {
+        Backend::Tree => sum::<SnailfishNum>(input)?.magnitude(),
+        Backend::Flat => sum::<FlatSnailfishNum>(input)?.magnitude(),
+    };
+    Ok(format!(
+        "magnitude {} in {:?}\n",
+        magnitude,
+        start.elapsed()
+    ))
+}
+
+/// Times both backends adding up the homework and finding its best pair.
+pub fn compare_backends(input: &str) -> Result<String, String> {
+    fn time<T: Snailfish + Send + Sync>(input: &str) -> Result<String, String> {
+        let start = Instant::now();
+        let sum = sum::<T>(input)?.magnitude();
+        let sum_time = start.elapsed();
+
+        let nums = parser::parse::<T>(input).map_err(|e| e.to_string())?.1;
+        let start = Instant::now();
+        let best = best_pairs(&nums, 1)
+            .first()
+            .ok_or("no pairs to add")?
+            .magnitude;
+        let best_time = start.elapsed();
+
+        Ok(format!(
+            "sum {} in {:?}, best pair {} in {:?}",
+            sum, sum_time, best, best_time
+        ))
+    }
+
+    Ok(format!(
+        "tree: {}\nflat: {}\n",
+        time::<SnailfishNum>(input)?,
+        time::<FlatSnailfishNum>(input)?
+    ))
+}
+
+fn sum<T: Snailfish>(input: &str) -> Result<T, String> {
+    let nums = parser::parse::<T>(input).map_err(|e| e.to_string())?.1;
+    nums.into_iter()
         .reduce(|mut a, b| {
             a.add(&b);
             a
         })
-        .unwrap();
-    ans
+        .ok_or_else(|| "no numbers to add".to_string())
+}
+
+/// Operations shared by the snailfish number backends.
+pub trait Snailfish: Clone + fmt::Display + Sized {
+    fn parse(input: &str) -> IResult<&str, Self>;
+    fn add(&mut self, other: &Self);
+    fn magnitude(&self) -> u64;
+    fn reduce_explode(&mut self) -> bool;
+    fn reduce_split(&mut self) -> bool;
+
+    fn reduce(&mut self) {
+        while self.reduce_explode() || self.reduce_split() {}
+    }
+}
+
+impl Snailfish for SnailfishNum {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        parser::num(input)
+    }
+
+    fn add(&mut self, other: &Self) {
+        SnailfishNum::add(self, other)
+    }
+
+    fn magnitude(&self) -> u64 {
+        SnailfishNum::magnitude(self)
+    }
+
+    fn reduce_explode(&mut self) -> bool {
+        SnailfishNum::reduce_explode(self)
+    }
+
+    fn reduce_split(&mut self) -> bool {
+        SnailfishNum::reduce_split(self)
+    }
+}
+
+impl Snailfish for FlatSnailfishNum {
+    fn parse(input: &str) -> IResult<&str, Self> {
+        parser::flat_num(input)
+    }
+
+    fn add(&mut self, other: &Self) {
+        FlatSnailfishNum::add(self, other)
+    }
+
+    fn magnitude(&self) -> u64 {
+        FlatSnailfishNum::magnitude(self)
+    }
+
+    fn reduce_explode(&mut self) -> bool {
+        FlatSnailfishNum::reduce_explode(self)
+    }
+
+    fn reduce_split(&mut self) -> bool {
+        FlatSnailfishNum::reduce_split(self)
+    }
+}
+
+mod flat {
+    use super::*;
+
+    /// A snailfish number stored as its literals in order, each tagged with the
+    /// number of pairs enclosing it.
+    #[derive(Clone, Debug, Default)]
+    pub struct FlatSnailfishNum {
+        entries: Vec<FlatEntry>,
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    struct FlatEntry {
+        value: u64,
+        depth: usize,
+    }
+
+    impl FlatEntry {
+        fn new(value: u64, depth: usize) -> Self {
+            FlatEntry { value, depth }
+        }
+    }
+
+    impl FlatSnailfishNum {
+        pub fn add(&mut self, other: &FlatSnailfishNum) {
+            self.entries.extend_from_slice(&other.entries);
+            for e in &mut self.entries {
+                e.depth += 1;
+            }
+
+            Snailfish::reduce(self);
+        }
+
+        pub fn magnitude(&self) -> u64 {
+            // each stack entry is a finished subtree. Two subtrees at the same
+            // depth next to each other are always siblings.
+            let mut stack: Vec<FlatEntry> = Vec::new();
+            for &e in &self.entries {
+                let mut cur = e;
+                while let Some(top) = stack.last() {
+                    if top.depth != cur.depth {
+                        break;
+                    }
+                    let left = stack.pop().unwrap();
+                    cur = FlatEntry::new(left.value * 3 + cur.value * 2, cur.depth - 1);
+                }
+                stack.push(cur);
+            }
+
+            stack[0].value
+        }
+
+        pub fn reduce_explode(&mut self) -> bool {
+            let i = match self.entries.iter().position(|e| e.depth > 4) {
+                Some(i) => i,
+                None => return false,
+            };
+
+            let (left, right) = (self.entries[i], self.entries[i + 1]);
+
+            if let Some(prev) = i.checked_sub(1) {
+                self.entries[prev].value += left.value;
+            }
+
+            if let Some(next) = self.entries.get_mut(i + 2) {
+                next.value += right.value;
+            }
+
+            self.entries[i] = FlatEntry::new(0, left.depth - 1);
+            self.entries.remove(i + 1);
+            true
+        }
+
+        pub fn reduce_split(&mut self) -> bool {
+            let i = match self.entries.iter().position(|e| e.value > 9) {
+                Some(i) => i,
+                None => return false,
+            };
+
+            let FlatEntry { value, depth } = self.entries[i];
+            self.entries[i] = FlatEntry::new(value / 2, depth + 1);
+            self.entries
+                .insert(i + 1, FlatEntry::new(value.div_ceil(2), depth + 1));
+            true
+        }
+    }
+
+    impl From<&SnailfishNum> for FlatSnailfishNum {
+        fn from(num: &SnailfishNum) -> Self {
+            let mut entries = Vec::new();
+            let mut walker = TreeWalker::new();
+            while let Some(node) = walker.next(num) {
+                let value = num.nodes[node].unwrap_literal();
+                entries.push(FlatEntry::new(value, walker.depth() - 1));
+            }
+
+            // a bare literal has no children for the walker to visit.
+            if entries.is_empty() {
+                entries.push(FlatEntry::new(num.nodes[num.root].unwrap_literal(), 0));
+            }
+
+            FlatSnailfishNum { entries }
+        }
+    }
+
+    impl fmt::Display for FlatSnailfishNum {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            fn rec_fmt(
+                f: &mut fmt::Formatter<'_>,
+                entries: &[FlatEntry],
+                i: &mut usize,
+                depth: usize,
+            ) -> fmt::Result {
+                let e = entries[*i];
+                if e.depth == depth {
+                    *i += 1;
+                    return write!(f, "{}", e.value);
+                }
+
+                f.write_char('[')?;
+                rec_fmt(f, entries, i, depth + 1)?;
+                f.write_char(',')?;
+                rec_fmt(f, entries, i, depth + 1)?;
+                f.write_char(']')
+            }
+
+            rec_fmt(f, &self.entries, &mut 0, 0)
+        }
+    }
 }
 
 mod tree_walker {
@@ -62,6 +434,11 @@ mod tree_walker {
             self.stack.len() + 1
         }
 
+        /// The turns taken from the root to reach the cursor.
+        pub fn path(&self) -> Vec<Direction> {
+            self.stack.iter().map(|e| e.dir).collect()
+        }
+
         pub fn left(&mut self, num: &SnailfishNum) -> Option<usize> {
             let cur = self.cursor(num);
             let next = num.nodes[cur].as_pair()?.left;
@@ -132,7 +509,7 @@ mod tree_walker {
     }
 
     #[derive(PartialEq, Eq, Clone, Copy, Debug)]
-    enum Direction {
+    pub enum Direction {
         Left,
         Right,
     }
@@ -142,6 +519,8 @@ mod tree_walker {
 pub struct SnailfishNum {
     root: usize,
     nodes: Vec<SnailfishNode>,
+    // slots in nodes that are no longer reachable from root.
+    free: Vec<usize>,
 }
 
 impl SnailfishNum {
@@ -149,12 +528,25 @@ impl SnailfishNum {
         Self {
             root: root,
             nodes: nodes,
+            free: Vec::new(),
         }
     }
 
     fn add(&mut self, other: &SnailfishNum) {
+        self.join(other);
+        Snailfish::reduce(self);
+
+        // most of the arena is garbage, so stop carrying it around.
+        if self.allocated_nodes() > 2 * self.live_nodes() {
+            self.compact();
+        }
+    }
+
+    /// Makes self the pair [self,other] without reducing it.
+    fn join(&mut self, other: &SnailfishNum) {
         let offset = self.nodes.len();
         self.nodes.extend_from_slice(&other.nodes);
+        self.free.extend(other.free.iter().map(|&i| i + offset));
 
         // point new nodes at the new locations.
         for n in &mut self.nodes[offset..] {
@@ -167,8 +559,6 @@ impl SnailfishNum {
         // add new root node
         let new_root = SnailfishNode::from((self.root, other.root + offset));
         self.root = self.add_node(new_root);
-
-        self.reduce();
     }
 
     fn magnitude(&self) -> u64 {
@@ -182,28 +572,65 @@ impl SnailfishNum {
         m_rec(self, self.root)
     }
 
-    fn reduce(&mut self) {
-        loop {
-            if self.reduce_explode() {
-                continue;
-            }
+    /// Number of nodes reachable from the root.
+    fn live_nodes(&self) -> usize {
+        self.nodes.len() - self.free.len()
+    }
 
-            if self.reduce_split() {
-                continue;
-            }
+    /// Number of node slots held by the arena, live or free.
+    fn allocated_nodes(&self) -> usize {
+        self.nodes.len()
+    }
 
-            break;
+    /// Rewrites the arena so it only holds live nodes, dropping the free list.
+    fn compact(&mut self) {
+        fn copy_rec(old: &[SnailfishNode], new: &mut Vec<SnailfishNode>, i: usize) -> usize {
+            let node = match old[i] {
+                SnailfishNode::Literal(l) => SnailfishNode::Literal(l),
+                SnailfishNode::Pair(p) => {
+                    let left = copy_rec(old, new, p.left);
+                    let right = copy_rec(old, new, p.right);
+                    SnailfishNode::from((left, right))
+                }
+            };
+            new.push(node);
+            new.len() - 1
         }
+
+        let mut nodes = Vec::with_capacity(self.live_nodes());
+        self.root = copy_rec(&self.nodes, &mut nodes, self.root);
+        self.nodes = nodes;
+        self.free.clear();
+    }
+
+    /// Yields every state reduce passes through along with the action that
+    /// produced it. The last item is the fully reduced number.
+    pub fn reduce_trace(&self) -> impl Iterator<Item = (ReduceAction, SnailfishNum)> {
+        let mut num = self.clone();
+        iter::from_fn(move || {
+            let action = match num.explode() {
+                Some(path) => ReduceAction::Explode(path),
+                None => ReduceAction::Split(num.split()?),
+            };
+            Some((action, num.clone()))
+        })
     }
 
     fn reduce_explode(&mut self) -> bool {
+        self.explode().is_some()
+    }
+
+    fn reduce_split(&mut self) -> bool {
+        self.split().is_some()
+    }
+
+    /// Explodes the leftmost pair nested inside four pairs, returning its path.
+    fn explode(&mut self) -> Option<Vec<Direction>> {
         let mut walker = TreeWalker::new();
 
         // find pair to explode
         loop {
-            if walker.next(self).is_none() {
-                return false;
-            }
+            walker.next(self)?;
 
             if walker.depth() > 5 {
                 walker.up(self);
@@ -218,10 +645,14 @@ impl SnailfishNum {
             (left, right)
         };
 
-        // replace exploding pair with 0
+        // replace exploding pair with 0 and release its children
         let cur = walker.cursor(self);
+        let pair = *self.nodes[cur].as_pair().unwrap();
+        self.free.push(pair.left);
+        self.free.push(pair.right);
         self.nodes[cur] = SnailfishNode::default();
 
+        let path = walker.path();
         let mut walker2 = walker.clone();
 
         if let Some(left_num) = walker.prev(self) {
@@ -234,10 +665,11 @@ impl SnailfishNum {
             self.nodes[right_num] = (v + exploding_pair.1).into();
         }
 
-        true
+        Some(path)
     }
 
-    fn reduce_split(&mut self) -> bool {
+    /// Splits the leftmost literal of 10 or more, returning its path.
+    fn split(&mut self) -> Option<Vec<Direction>> {
         let mut walker = TreeWalker::new();
         while let Some(node) = walker.next(self) {
             let n = self.nodes[node].unwrap_literal();
@@ -246,14 +678,19 @@ impl SnailfishNum {
                 let right = self.add_node(SnailfishNode::from((n + 1) / 2));
 
                 self.nodes[node] = SnailfishNode::from((left, right));
-                return true;
+                return Some(walker.path());
             }
         }
 
-        false
+        None
     }
 
     fn add_node(&mut self, node: SnailfishNode) -> usize {
+        if let Some(id) = self.free.pop() {
+            self.nodes[id] = node;
+            return id;
+        }
+
         let id = self.nodes.len();
         self.nodes.push(node);
         id
@@ -280,6 +717,67 @@ impl fmt::Display for SnailfishNum {
     }
 }
 
+impl PartialEq for SnailfishNum {
+    fn eq(&self, other: &Self) -> bool {
+        fn eq_rec(a: &SnailfishNum, i: usize, b: &SnailfishNum, j: usize) -> bool {
+            match (&a.nodes[i], &b.nodes[j]) {
+                (SnailfishNode::Literal(x), SnailfishNode::Literal(y)) => x == y,
+                (SnailfishNode::Pair(x), SnailfishNode::Pair(y)) => {
+                    eq_rec(a, x.left, b, y.left) && eq_rec(a, x.right, b, y.right)
+                }
+                _ => false,
+            }
+        }
+
+        eq_rec(self, self.root, other, other.root)
+    }
+}
+
+impl Eq for SnailfishNum {}
+
+impl ops::Add for SnailfishNum {
+    type Output = SnailfishNum;
+
+    fn add(mut self, other: SnailfishNum) -> SnailfishNum {
+        SnailfishNum::add(&mut self, &other);
+        self
+    }
+}
+
+impl ops::Add<&SnailfishNum> for SnailfishNum {
+    type Output = SnailfishNum;
+
+    fn add(mut self, other: &SnailfishNum) -> SnailfishNum {
+        SnailfishNum::add(&mut self, other);
+        self
+    }
+}
+
+/// Adds the numbers in order. Snailfish numbers have no zero, so there's no
+/// sum without at least one of them.
+impl iter::Sum<SnailfishNum> for Option<SnailfishNum> {
+    fn sum<I: Iterator<Item = SnailfishNum>>(iter: I) -> Option<SnailfishNum> {
+        iter.reduce(|a, b| a + b)
+    }
+}
+
+impl FromStr for SnailfishNum {
+    type Err = nom::error::Error<String>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        complete(parser::num)(s)
+            .finish()
+            .map(|(_, num)| num)
+            .map_err(|e| nom::error::Error::new(e.input.to_string(), e.code))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReduceAction {
+    Explode(Vec<Direction>),
+    Split(Vec<Direction>),
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum SnailfishNode {
     Pair(SnailfishPair),
@@ -342,7 +840,7 @@ impl From<(usize, usize)> for SnailfishPair {
 }
 
 mod parser {
-    use super::{SnailfishNode, SnailfishNum};
+    use super::{FlatSnailfishNum, Snailfish, SnailfishNode, SnailfishNum};
     use crate::lib::combinators::*;
     use std::cell::RefCell;
 
@@ -388,8 +886,8 @@ mod parser {
         }
     }
 
-    pub fn parse(input: &str) -> IResult<&str, Vec<SnailfishNum>> {
-        let parser = separated_list1(line_ending, num);
+    pub fn parse<T: Snailfish>(input: &str) -> IResult<&str, Vec<T>> {
+        let parser = separated_list1(line_ending, T::parse);
         complete(parser)(input)
     }
 
@@ -399,12 +897,20 @@ mod parser {
         let ret = SnailfishNum::from_nodes(parser.into_inner(), root);
         Ok((rest, ret))
     }
+
+    pub fn flat_num(input: &str) -> IResult<&str, FlatSnailfishNum> {
+        map(num, |n| FlatSnailfishNum::from(&n))(input)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn add_nums<T: Snailfish>(input: &str) -> T {
+        sum(input).unwrap()
+    }
+
     const EXAMPLE_INPUT: &str = "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
 [[[5,[2,8]],4],[5,[[9,9],0]]]
 [6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
@@ -473,7 +979,8 @@ mod tests {
         ];
 
         for (expected, input) in tests {
-            assert_eq!(format!("{}", add_nums(input)), expected);
+            assert_eq!(format!("{}", add_nums::<SnailfishNum>(input)), expected);
+            assert_eq!(format!("{}", add_nums::<FlatSnailfishNum>(input)), expected);
         }
     }
 
@@ -497,11 +1004,236 @@ mod tests {
             ),
         ];
 
-        for (input, expected) in tests {
-            let mut num = parser::num(input).unwrap().1;
+        fn check<T: Snailfish>(input: &str, expected: &str) {
+            let mut num = T::parse(input).unwrap().1;
             let ret = num.reduce_explode();
             assert_eq!(ret, true);
             assert_eq!(format!("{}", num), expected);
         }
+
+        for (input, expected) in tests {
+            check::<SnailfishNum>(input, expected);
+            check::<FlatSnailfishNum>(input, expected);
+        }
+    }
+
+    #[test]
+    fn backends_agree_test() {
+        let tree = parser::parse::<SnailfishNum>(EXAMPLE_INPUT).unwrap().1;
+        let flat = parser::parse::<FlatSnailfishNum>(EXAMPLE_INPUT).unwrap().1;
+
+        assert_eq!("flat".parse(), Ok(Backend::Flat));
+        assert!("list".parse::<Backend>().is_err());
+        for backend in [Backend::Tree, Backend::Flat] {
+            let report = sum_report(EXAMPLE_INPUT, backend).unwrap();
+            assert!(report.starts_with("magnitude 4140 in "));
+        }
+        let report = compare_backends(EXAMPLE_INPUT).unwrap();
+        assert!(report.starts_with("tree: sum 4140 in "));
+        assert!(report.contains("\nflat: sum 4140 in "));
+        assert!(report.contains(", best pair 3993 in "));
+        assert!(compare_backends("[1,2]").is_err());
+
+        for (t, f) in tree.iter().zip(&flat) {
+            assert_eq!(format!("{}", t), format!("{}", f));
+            assert_eq!(t.magnitude(), f.magnitude());
+        }
+
+        for i in 0..tree.len() {
+            for j in 0..tree.len() {
+                let mut t = tree[i].clone();
+                let mut f = flat[i].clone();
+                t.add(&tree[j]);
+                f.add(&flat[j]);
+                assert_eq!(format!("{}", t), format!("{}", f));
+                assert_eq!(t.magnitude(), f.magnitude());
+            }
+        }
+    }
+
+    #[test]
+    fn arena_reuse_test() {
+        let num = add_nums::<SnailfishNum>(EXAMPLE_INPUT);
+
+        let mut reachable = 0;
+        let mut stack = vec![num.root];
+        while let Some(i) = stack.pop() {
+            reachable += 1;
+            if let Some(p) = num.nodes[i].as_pair() {
+                stack.extend([p.left, p.right]);
+            }
+        }
+        assert_eq!(num.live_nodes(), reachable);
+
+        // freed slots are reused, so the arena stays close to the sum of its inputs.
+        let input_nodes: usize = parser::parse::<SnailfishNum>(EXAMPLE_INPUT)
+            .unwrap()
+            .1
+            .iter()
+            .map(|n| n.allocated_nodes())
+            .sum();
+        assert!(num.allocated_nodes() <= input_nodes + 2 * EXAMPLE_INPUT.lines().count());
+
+        let mut compacted = num.clone();
+        compacted.compact();
+        assert_eq!(compacted.allocated_nodes(), reachable);
+        assert_eq!(format!("{}", compacted), format!("{}", num));
+    }
+
+    #[test]
+    fn operators_test() {
+        let a: SnailfishNum = "[[[[4,3],4],4],[7,[[8,4],9]]]".parse().unwrap();
+        let b: SnailfishNum = "[1,1]".parse().unwrap();
+        let expected: SnailfishNum = "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]".parse().unwrap();
+        assert_eq!(a.clone() + &b, expected);
+        assert_eq!(a + b, expected);
+
+        let nums = parser::parse::<SnailfishNum>(EXAMPLE_INPUT).unwrap().1;
+        let sum: Option<SnailfishNum> = nums.into_iter().sum();
+        assert_eq!(sum.unwrap().magnitude(), 4140);
+        let empty: Option<SnailfishNum> = iter::empty().sum();
+        assert_eq!(empty, None);
+
+        assert!("[1,2".parse::<SnailfishNum>().is_err());
+        assert!("[1,2] x".parse::<SnailfishNum>().is_err());
+    }
+
+    #[test]
+    fn reduce_trace_test() {
+        use Direction::{Left as L, Right as R};
+
+        let mut num: SnailfishNum = "[[[[4,3],4],4],[7,[[8,4],9]]]".parse().unwrap();
+        num.join(&"[1,1]".parse().unwrap());
+        assert_eq!(format!("{}", num), "[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]");
+
+        let expected = [
+            (
+                ReduceAction::Explode(vec![L, L, L, L]),
+                "[[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+            ),
+            (
+                ReduceAction::Explode(vec![L, R, R, L]),
+                "[[[[0,7],4],[15,[0,13]]],[1,1]]",
+            ),
+            (
+                ReduceAction::Split(vec![L, R, L]),
+                "[[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+            ),
+            (
+                ReduceAction::Split(vec![L, R, R, R]),
+                "[[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+            ),
+            (
+                ReduceAction::Explode(vec![L, R, R, R]),
+                "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+            ),
+        ];
+
+        let trace: Vec<_> = num
+            .reduce_trace()
+            .map(|(action, n)| (action, format!("{}", n)))
+            .collect();
+        let expected: Vec<_> = expected
+            .into_iter()
+            .map(|(action, n)| (action, n.to_string()))
+            .collect();
+        assert_eq!(trace, expected);
+
+        let report = reduction_report("[[[[4,3],4],4],[7,[[8,4],9]]]\n[1,1]").unwrap();
+        assert!(report.contains("\nsplit   LRL   [[[[0,7],4],[[7,8],[0,13]]],[1,1]]\n"));
+        assert!(report.ends_with("= [[[[0,7],4],[[7,8],[6,0]]],[8,1]]\n\nmagnitude 1384\n"));
+        assert!(reduction_report("[1,2").is_err());
+    }
+
+    #[test]
+    fn best_pairs_test() {
+        let nums = parser::parse::<SnailfishNum>(EXAMPLE_INPUT).unwrap().1;
+        let best = best_pairs(&nums, 5);
+
+        assert_eq!(best.len(), 5);
+        assert_eq!(best[0].magnitude, 3993);
+        assert_eq!((best[0].left_index, best[0].right_index), (8, 0));
+        assert_eq!(best[0].left, "[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]");
+        assert_eq!(
+            best[0].right,
+            "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]"
+        );
+        assert!(best.windows(2).all(|w| w[0].magnitude >= w[1].magnitude));
+
+        let flat = parser::parse::<FlatSnailfishNum>(EXAMPLE_INPUT).unwrap().1;
+        assert_eq!(best_pairs(&flat, 5), best);
+
+        // asking for more than there are gives every ordered pair, ties in
+        // index order.
+        let nums: Vec<SnailfishNum> = ["[1,1]", "[1,1]", "[2,2]"]
+            .iter()
+            .map(|n| n.parse().unwrap())
+            .collect();
+        let pairs: Vec<_> = best_pairs(&nums, 10)
+            .iter()
+            .map(|p| (p.magnitude, p.left_index, p.right_index))
+            .collect();
+        assert_eq!(
+            pairs,
+            [
+                (40, 2, 0),
+                (40, 2, 1),
+                (35, 0, 2),
+                (35, 1, 2),
+                (25, 0, 1),
+                (25, 1, 0)
+            ]
+        );
+        assert!(best_pairs(&nums, 0).is_empty());
+
+        let report = best_pairs_report(EXAMPLE_INPUT, 2, Backend::Flat).unwrap();
+        let lines: Vec<_> = report.lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[0], "1. numbers 8 + 0, magnitude 3993");
+        assert_eq!(lines[1], "     [[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]");
+        assert_eq!(
+            lines[2],
+            "   + [[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]"
+        );
+        assert!(lines[3].starts_with("2. numbers "));
+        assert_eq!(
+            best_pairs_report(EXAMPLE_INPUT, 2, Backend::Tree),
+            Ok(report)
+        );
+    }
+
+    // A random reduced number, drawn from an LCG so every run sees the same.
+    fn random_num(seed: &mut u64, depth: usize) -> String {
+        *seed = seed
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        let r = *seed >> 33;
+        if depth == 4 || (depth > 0 && r.is_multiple_of(3)) {
+            return (r % 10).to_string();
+        }
+        let left = random_num(seed, depth + 1);
+        format!("[{},{}]", left, random_num(seed, depth + 1))
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release"]
+    fn backend_benchmark() {
+        let mut seed = 18;
+        let input: Vec<_> = (0..1000).map(|_| random_num(&mut seed, 0)).collect();
+        let input = input.join("\n");
+
+        // sums the whole homework, then finds the best pair among the first 200.
+        fn time<T: Snailfish + Send + Sync>(input: &str) -> (u64, u64, std::time::Duration) {
+            let start = std::time::Instant::now();
+            let sum = add_nums::<T>(input).magnitude();
+            let nums = parser::parse::<T>(input).unwrap().1;
+            let best = best_pairs(&nums[..200], 1)[0].magnitude;
+            (sum, best, start.elapsed())
+        }
+        let (tree_sum, tree_best, tree) = time::<SnailfishNum>(&input);
+        let (flat_sum, flat_best, flat) = time::<FlatSnailfishNum>(&input);
+        println!("tree {:?}, flat {:?}", tree, flat);
+
+        assert_eq!((tree_sum, tree_best), (flat_sum, flat_best));
     }
 }
@@ -1,27 +1,22 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fmt;
+use std::fmt::Write;
 
-use rayon::iter::Either;
 use rayon::prelude::*;
 
+use crate::lib::geom::{Point, Rotation, Vector};
+use crate::lib::io::write_sep_ln;
+
 pub fn problem1(input: &str) -> String {
     let unsolved_scanners = parser::parse(input).unwrap().1;
-    let solved_scanners = solve_scanners(unsolved_scanners);
-
-    let mut beacons: Vec<_> = solved_scanners
-        .iter()
-        .flat_map(|s| s.beacons.iter())
-        .collect();
-
-    beacons.sort();
-    beacons.dedup();
-
-    format!("{}", beacons.len())
+    let solution = solve_scanners(unsolved_scanners, &MatchConfig::default());
+    format!("{}", solution.beacons().len())
 }
 
 pub fn problem2(input: &str) -> String {
     let unsolved_scanners = parser::parse(input).unwrap().1;
-    let solved_scanners = solve_scanners(unsolved_scanners);
+    let solved_scanners = solve_scanners(unsolved_scanners, &MatchConfig::default()).placed;
 
     let scanner_coordinates: Vec<_> = solved_scanners.iter().map(|s| s.location).collect();
 
@@ -29,100 +24,414 @@ pub fn problem2(input: &str) -> String {
 
     for a in scanner_coordinates.iter() {
         for b in scanner_coordinates.iter() {
-            max = max.max(a.distance(b));
+            max = max.max(a.manhattan(b));
         }
     }
 
     format!("{}", max)
 }
 
-fn solve_scanners(mut scanners: Vec<Scanner>) -> Vec<Scanner> {
+/// Aligns the scanners and renders the result as an alignment tree, or as
+/// CSV or PLY for other tools.
+pub fn export(input: &str, format: &str) -> Result<String, String> {
+    let scanners = parser::parse(input).map_err(|e| e.to_string())?.1;
+    let solution = solve_scanners(scanners, &MatchConfig::default());
+    match format {
+        "tree" => Ok(format!("{}{}\n", solution.alignment_tree(), solution.stats)),
+        "csv" => Ok(solution.to_csv()),
+        "ply" => Ok(solution.to_ply()),
+        _ => Err(format!("unknown format {}, try tree, csv or ply", format)),
+    }
+}
+
+/// Aligns the scanners and lists where each one sits and how it's turned, in
+/// the coordinates of the scanner with the given id.
+pub fn relative_poses(input: &str, reference: i32) -> Result<String, String> {
+    let scanners = parser::parse(input).map_err(|e| e.to_string())?.1;
+    let solution = solve_scanners(scanners, &MatchConfig::default());
+    let poses = solution
+        .relative_poses(reference)
+        .ok_or(format!("scanner {} wasn't placed", reference))?;
+
+    let mut out = String::new();
+    for (id, pose) in poses {
+        let location = pose.apply(&Point::ORIGIN);
+        let rotation = pose.rotation.matrix();
+        writeln!(
+            out,
+            "scanner {} at {:?} rotation {:?}",
+            id, location, rotation
+        )
+        .unwrap();
+    }
+    Ok(out)
+}
+
+/// Controls how scanners are matched against each other.
+#[derive(Clone, Copy, Debug)]
+pub struct MatchConfig {
+    /// Number of beacons two scanners must have in common to be aligned.
+    pub min_overlap: usize,
+    /// Largest per-axis disagreement allowed between two scanners' readings of
+    /// the same beacon. Zero requires exact matches.
+    pub tolerance: i32,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            min_overlap: 12,
+            tolerance: 0,
+        }
+    }
+}
+
+/// The outcome of aligning every scanner to the first one.
+pub struct ScannerSolution {
+    /// Scanners moved into the first scanner's coordinate system.
+    pub placed: Vec<Scanner>,
+    /// Scanners that couldn't be aligned to any placed scanner, left untouched.
+    pub unplaced: Vec<Scanner>,
+    pub stats: MatchStats,
+}
+
+impl ScannerSolution {
+    /// Each placed scanner's pose in the coordinates of the scanner with the
+    /// given id, sorted by id. Returns None if that scanner wasn't placed.
+    pub fn relative_poses(&self, reference: i32) -> Option<Vec<(i32, Transformation)>> {
+        let reference = self.placed.iter().find(|s| s.id == reference)?;
+        let to_reference = reference.pose.inverse();
+
+        let mut ret: Vec<_> = self
+            .placed
+            .iter()
+            .map(|s| (s.id, to_reference.compose(&s.pose)))
+            .collect();
+        ret.sort_by_key(|(id, _)| *id);
+        Some(ret)
+    }
+
+    /// Where each placed scanner ended up and how it got there, sorted by id.
+    pub fn report(&self) -> Vec<ScannerReport> {
+        let mut ret: Vec<_> = self
+            .placed
+            .iter()
+            .map(|s| ScannerReport {
+                id: s.id,
+                location: s.location,
+                rotation: s.pose.rotation,
+                alignment: s.alignment,
+            })
+            .collect();
+        ret.sort_by_key(|r| r.id);
+        ret
+    }
+
+    /// Renders the placed scanners as a tree, each indented under the scanner
+    /// it was aligned against, followed by any unplaced scanners.
+    pub fn alignment_tree(&self) -> String {
+        fn write_rec(out: &mut String, reports: &[ScannerReport], id: i32, depth: usize) {
+            let r = reports.iter().find(|r| r.id == id).unwrap();
+            writeln!(out, "{}{}", "  ".repeat(depth), r).unwrap();
+            for child in reports.iter() {
+                if child.alignment.map(|a| a.parent) == Some(id) {
+                    write_rec(out, reports, child.id, depth + 1);
+                }
+            }
+        }
+
+        let reports = self.report();
+        let mut out = String::new();
+        for root in reports.iter().filter(|r| r.alignment.is_none()) {
+            write_rec(&mut out, &reports, root.id, 0);
+        }
+
+        if !self.unplaced.is_empty() {
+            let mut ids: Vec<_> = self.unplaced.iter().map(|s| s.id).collect();
+            ids.sort();
+            out.push_str("unplaced: ");
+            write_sep_ln(&mut out, ", ", ids.iter()).unwrap();
+        }
+
+        out
+    }
+
+    /// Every distinct beacon seen by a placed scanner.
+    pub fn beacons(&self) -> Vec<Point<3>> {
+        let mut beacons: Vec<_> = self
+            .placed
+            .iter()
+            .flat_map(|s| s.beacons.iter().copied())
+            .collect();
+        beacons.sort();
+        beacons.dedup();
+        beacons
+    }
+
+    /// The placed scanners and merged beacons as CSV rows of kind,id,x,y,z.
+    /// Beacons have no id.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("kind,id,x,y,z\n");
+        for r in self.report() {
+            let l = r.location;
+            writeln!(out, "scanner,{},{},{},{}", r.id, l[0], l[1], l[2]).unwrap();
+        }
+        for b in self.beacons() {
+            writeln!(out, "beacon,,{},{},{}", b[0], b[1], b[2]).unwrap();
+        }
+        out
+    }
+
+    /// The placed scanners (red) and merged beacons (white) as an ASCII PLY
+    /// point cloud.
+    pub fn to_ply(&self) -> String {
+        let scanners = self.report();
+        let beacons = self.beacons();
+
+        let mut out = String::new();
+        writeln!(out, "ply").unwrap();
+        writeln!(out, "format ascii 1.0").unwrap();
+        writeln!(out, "comment scanners are red, beacons are white").unwrap();
+        writeln!(out, "element vertex {}", scanners.len() + beacons.len()).unwrap();
+        for p in ["x", "y", "z"] {
+            writeln!(out, "property int {}", p).unwrap();
+        }
+        for p in ["red", "green", "blue"] {
+            writeln!(out, "property uchar {}", p).unwrap();
+        }
+        writeln!(out, "end_header").unwrap();
+
+        let scanner_rows = scanners.iter().map(|r| (r.location, [255, 0, 0]));
+        let beacon_rows = beacons.iter().map(|&b| (b, [255, 255, 255]));
+        for (p, color) in scanner_rows.chain(beacon_rows) {
+            let row = [p[0], p[1], p[2], color[0], color[1], color[2]];
+            write_sep_ln(&mut out, " ", row.iter()).unwrap();
+        }
+        out
+    }
+}
+
+/// A placed scanner's position and orientation relative to the first scanner.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScannerReport {
+    pub id: i32,
+    pub location: Point<3>,
+    pub rotation: Rotation<3>,
+    /// None for the scanner everything else is placed relative to.
+    pub alignment: Option<Alignment>,
+}
+
+impl fmt::Display for ScannerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "scanner {} at {:?} rotation {:?}",
+            self.id,
+            self.location,
+            self.rotation.matrix()
+        )?;
+        if let Some(a) = self.alignment {
+            write!(f, " via scanner {} ({} beacons)", a.parent, a.overlap)?;
+        }
+        Ok(())
+    }
+}
+
+/// The scanner another scanner was matched against and how many beacons the
+/// two had in common.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Alignment {
+    pub parent: i32,
+    pub overlap: usize,
+}
+
+fn solve_scanners(mut scanners: Vec<Scanner>, config: &MatchConfig) -> ScannerSolution {
     let mut useful_scanners = Vec::new();
     let mut done_scanners = Vec::new();
+    let mut stats = MatchStats::default();
+
+    if !scanners.is_empty() {
+        useful_scanners.push(scanners.swap_remove(0));
+    }
 
-    useful_scanners.push(scanners.swap_remove(0));
     while let Some(cur) = useful_scanners.pop() {
-        let (new_useful, not_ready): (Vec<_>, Vec<_>) =
-            scanners.par_drain(..).partition_map(|mut s| {
-                if let Some(transform) = cur.find_overlap(&s) {
-                    s.apply_transformation(&transform);
-                    Either::Left(s)
-                } else {
-                    Either::Right(s)
+        let results: Vec<_> = scanners
+            .par_drain(..)
+            .map(|mut s| {
+                let mut stats = MatchStats::default();
+                let transform = cur.find_overlap(&s, config, &mut stats);
+                if let Some((overlap, transform)) = &transform {
+                    let overlap = *overlap;
+                    s.apply_transformation(transform);
+                    s.alignment = Some(Alignment {
+                        parent: cur.id,
+                        overlap,
+                    });
                 }
-            });
-        useful_scanners.extend(new_useful);
-        scanners.extend(not_ready);
+                (s, transform.is_some(), stats)
+            })
+            .collect();
+
+        for (s, placed, s_stats) in results {
+            stats.merge(&s_stats);
+            if placed {
+                useful_scanners.push(s);
+            } else {
+                scanners.push(s);
+            }
+        }
         done_scanners.push(cur);
     }
 
-    assert!(scanners.is_empty());
-    done_scanners
+    ScannerSolution {
+        placed: done_scanners,
+        unplaced: scanners,
+        stats,
+    }
 }
 
-pub struct Scanner {
-    _id: i32,
-    location: Point,
-    beacons: Vec<Point>,
+/// Counters describing how much work scanner matching did.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MatchStats {
+    /// Scanner pairs that were compared.
+    pub scanner_pairs: usize,
+    /// Scanner pairs rejected because their fingerprints share too few distances.
+    pub fingerprint_rejects: usize,
+    /// Anchor beacon pairs handed to the full overlap check.
+    pub anchors_tried: usize,
+    /// Candidate transformations produced by anchors.
+    pub hypotheses: usize,
+    /// Scanner pairs that were successfully aligned.
+    pub matches: usize,
 }
 
-type PointDiff = Point;
+impl MatchStats {
+    fn merge(&mut self, other: &MatchStats) {
+        self.scanner_pairs += other.scanner_pairs;
+        self.fingerprint_rejects += other.fingerprint_rejects;
+        self.anchors_tried += other.anchors_tried;
+        self.hypotheses += other.hypotheses;
+        self.matches += other.matches;
+    }
+}
+
+impl fmt::Display for MatchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pairs compared, {} rejected by fingerprint, {} anchors tried, {} hypotheses, {} matched",
+            self.scanner_pairs,
+            self.fingerprint_rejects,
+            self.anchors_tried,
+            self.hypotheses,
+            self.matches
+        )
+    }
+}
+
+pub struct Scanner {
+    id: i32,
+    location: Point<3>,
+    // maps the scanner's own readings into the coordinates they are now in.
+    pose: Transformation,
+    alignment: Option<Alignment>,
+    beacons: Vec<Point<3>>,
+    fingerprint: Fingerprint,
+}
 
 impl Scanner {
-    fn find_overlap(&self, other: &Scanner) -> Option<Transformation> {
-        for sp in self.beacons.iter() {
-            for op in other.beacons.iter() {
-                if let Some(t) = self.overlap(other, sp, op) {
-                    return Some(t);
-                }
+    // Every anchor pair that survives the fingerprint check proposes a
+    // transformation. The one that lines up the most beacons wins, and comes
+    // back with how many it lines up.
+    fn find_overlap(
+        &self,
+        other: &Scanner,
+        config: &MatchConfig,
+        stats: &mut MatchStats,
+    ) -> Option<(usize, Transformation)> {
+        stats.scanner_pairs += 1;
+
+        // every pair of shared beacons contributes a shared distance.
+        let min_overlap = config.min_overlap;
+        let min_shared = min_overlap * min_overlap.saturating_sub(1) / 2;
+        // two readings of the same beacon pair can disagree by tolerance on
+        // each axis at both ends.
+        let dist_tolerance = 2.0 * 3f64.sqrt() * config.tolerance as f64;
+        if self.fingerprint.shared(&other.fingerprint, dist_tolerance) < min_shared {
+            stats.fingerprint_rejects += 1;
+            return None;
+        }
+
+        let anchors = self.fingerprint.anchors(
+            &other.fingerprint,
+            dist_tolerance,
+            min_overlap.saturating_sub(1),
+        );
+
+        let mut best: Option<(usize, Transformation)> = None;
+        for (sp, op) in anchors {
+            stats.anchors_tried += 1;
+            let t = match self.overlap(other, &self.beacons[sp], &other.beacons[op], config) {
+                Some(t) => t,
+                None => continue,
+            };
+
+            stats.hypotheses += 1;
+            if best.as_ref().is_some_and(|(_, b)| *b == t) {
+                continue;
             }
+
+            let inliers = self.inliers(other, &t, 2 * config.tolerance as i64);
+            if inliers >= min_overlap && best.as_ref().is_none_or(|(n, _)| inliers > *n) {
+                best = Some((inliers, t));
+            }
+        }
+
+        if best.is_some() {
+            stats.matches += 1;
         }
+        best
+    }
 
-        None
+    // Number of other's beacons that t moves onto one of self's beacons.
+    fn inliers(&self, other: &Scanner, t: &Transformation, tolerance: i64) -> usize {
+        other
+            .beacons
+            .iter()
+            .map(|b| t.apply(b))
+            .filter(|b| self.beacons.iter().any(|a| a.chebyshev(b) <= tolerance))
+            .count()
     }
 
     fn overlap(
         &self,
         other: &Scanner,
-        self_point: &Point,
-        other_point: &Point,
+        self_point: &Point<3>,
+        other_point: &Point<3>,
+        config: &MatchConfig,
     ) -> Option<Transformation> {
-        fn distance_diff_hashmap(
-            beacons: &[Point],
-            p: &Point,
-        ) -> HashMap<i32, Vec<(Point, PointDiff)>> {
-            let mut m = HashMap::new();
-
-            for b in beacons {
-                m.entry(p.distance(b))
-                    .or_insert_with(Vec::new)
-                    .push((*b, p.diff(b)));
-            }
-
-            m
-        }
-
-        let self_dists = distance_diff_hashmap(&self.beacons, self_point);
-        let other_dists = distance_diff_hashmap(&other.beacons, other_point);
+        // offsets from the anchor can disagree by tolerance at both ends.
+        let diff_tolerance = 2 * config.tolerance as i64;
 
         let mut candidates = Vec::new();
-        for (dist, diffs) in self_dists.iter() {
-            for diff in diffs.iter() {
-                if let Some(o_diffs) = other_dists.get(dist) {
-                    for other_diff in o_diffs {
-                        candidates.push((diff, other_diff));
-                    }
+        for a in self.beacons.iter() {
+            let a_diff = *a - *self_point;
+            for b in other.beacons.iter() {
+                let b_diff = *b - *other_point;
+                let dist_diff = a_diff.manhattan() - b_diff.manhattan();
+                if dist_diff.abs() <= 3 * diff_tolerance {
+                    candidates.push(((*a, a_diff), (*b, b_diff)));
                 }
             }
         }
 
-        if candidates.len() < 12 {
+        if candidates.len() < config.min_overlap {
             return None;
         }
 
         let mut rotations = HashMap::new();
         for (a, b) in candidates.iter() {
-            for rotation in Rotation::rotations_to_equal(&a.1, &b.1) {
+            for rotation in rotations_near(&a.1, &b.1, diff_tolerance) {
                 rotations
                     .entry(rotation)
                     .or_insert_with(Vec::new)
@@ -130,269 +439,177 @@ impl Scanner {
             }
         }
 
+        let mut best: Option<(usize, Transformation)> = None;
         for (rotation, pairs) in rotations {
-            if pairs.len() < 12 {
+            if pairs.len() < config.min_overlap {
                 continue;
             }
 
-            let mut by_translation = HashMap::new();
-            for (a, b) in pairs {
-                let diff = a.diff(&rotation.apply(&b));
-                by_translation
-                    .entry(diff)
-                    .or_insert_with(Vec::new)
-                    .push((a, b))
-            }
-
-            for (translation, pairs) in by_translation {
-                if pairs.len() >= 12 {
-                    return Some(Transformation {
-                        rotation: rotation,
-                        translation: translation,
-                    });
+            let translations: Vec<Vector<3>> = pairs
+                .iter()
+                .map(|(a, b)| *a - rotation.apply_point(*b))
+                .collect();
+
+            // each translation is a guess; keep the one most others agree with.
+            for t in translations.iter() {
+                let agreeing: Vec<Vector<3>> = translations
+                    .iter()
+                    .filter(|&&u| (u - *t).chebyshev() <= diff_tolerance)
+                    .copied()
+                    .collect();
+
+                if agreeing.len() < config.min_overlap
+                    || best.as_ref().is_some_and(|(n, _)| *n >= agreeing.len())
+                {
+                    continue;
                 }
+
+                let transformation = Transformation {
+                    rotation,
+                    translation: mean(&agreeing),
+                };
+                best = Some((agreeing.len(), transformation));
             }
         }
 
-        None
+        best.map(|(_, t)| t)
     }
 
     fn apply_transformation(&mut self, t: &Transformation) {
         self.location = t.apply(&self.location);
+        self.pose = t.compose(&self.pose);
         self.beacons.iter_mut().for_each(|x| *x = t.apply(x));
     }
 }
 
-impl From<(i32, Vec<Point>)> for Scanner {
-    fn from(s: (i32, Vec<Point>)) -> Self {
+impl From<(i32, Vec<Point<3>>)> for Scanner {
+    fn from(s: (i32, Vec<Point<3>>)) -> Self {
         Scanner {
-            _id: s.0,
-            location: Point::default(),
+            id: s.0,
+            location: Point::ORIGIN,
+            pose: Transformation::default(),
+            alignment: None,
+            fingerprint: Fingerprint::new(&s.1),
             beacons: s.1,
         }
     }
 }
 
-#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct Point {
-    x: i32,
-    y: i32,
-    z: i32,
+/// Distances between every pair of beacons a scanner sees. They don't change
+/// when a scanner is rotated or moved, so two scanners that see the same
+/// beacons share them.
+#[derive(Clone, Debug, Default)]
+struct Fingerprint {
+    // (distance, i, j) for beacons i < j, sorted by distance.
+    pairs: Vec<(f64, usize, usize)>,
 }
 
-impl Point {
-    fn translate(&self, diff: &PointDiff) -> Point {
-        Point {
-            x: self.x + diff.x,
-            y: self.y + diff.y,
-            z: self.z + diff.z,
-        }
-    }
-
-    fn diff(&self, other: &Point) -> PointDiff {
-        PointDiff {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z,
+impl Fingerprint {
+    fn new(beacons: &[Point<3>]) -> Self {
+        let mut pairs = Vec::new();
+        for (i, a) in beacons.iter().enumerate() {
+            for (j, b) in beacons.iter().enumerate().skip(i + 1) {
+                pairs.push(((a.distance_squared(b) as f64).sqrt(), i, j));
+            }
         }
-    }
+        pairs.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
-    // Computes manhattan distance.
-    fn distance(&self, other: &Point) -> i32 {
-        let x_diff = (self.x - other.x).abs();
-        let y_diff = (self.y - other.y).abs();
-        let z_diff = (self.z - other.z).abs();
-
-        x_diff + y_diff + z_diff
+        Fingerprint { pairs }
     }
-}
 
-impl From<(i32, i32, i32)> for Point {
-    fn from(p: (i32, i32, i32)) -> Self {
-        Point {
-            x: p.0,
-            y: p.1,
-            z: p.2,
-        }
+    // Pairs whose distance is within tolerance of d.
+    fn near(&self, d: f64, tolerance: f64) -> &[(f64, usize, usize)] {
+        let start = self.pairs.partition_point(|p| p.0 < d - tolerance);
+        let end = self.pairs.partition_point(|p| p.0 <= d + tolerance);
+        &self.pairs[start..end]
     }
-}
 
-impl fmt::Debug for Point {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "({}, {}, {})", self.x, self.y, self.z)
+    // Number of self's distances that other also has.
+    fn shared(&self, other: &Fingerprint, tolerance: f64) -> usize {
+        self.pairs
+            .iter()
+            .filter(|p| !other.near(p.0, tolerance).is_empty())
+            .count()
     }
-}
-
-impl fmt::Display for Point {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct Rotation {
-    swaps: (Axis, Axis, Axis),
-    negations: (bool, bool, bool),
-}
-
-impl Rotation {
-    fn rotations_to_equal(a: &Point, b: &Point) -> Vec<Rotation> {
-        fn sorted_abs_values(p: &Point) -> [i32; 3] {
-            let mut ret = [p.x.abs(), p.y.abs(), p.z.abs()];
-            ret.sort();
-            ret
-        }
 
-        if sorted_abs_values(a) != sorted_abs_values(b) {
-            return Vec::new();
-        }
-
-        let target = (a.x.abs(), a.y.abs(), a.z.abs());
-
-        let permutations = [
-            (Axis::X, Axis::Y, Axis::Z),
-            (Axis::X, Axis::Z, Axis::Y),
-            (Axis::Y, Axis::X, Axis::Z),
-            (Axis::Y, Axis::Z, Axis::X),
-            (Axis::Z, Axis::Y, Axis::X),
-            (Axis::Z, Axis::X, Axis::Y),
-        ];
-
-        let swaps = permutations.iter().filter(|swap| {
-            (
-                swap.0.value(b).abs(),
-                swap.1.value(b).abs(),
-                swap.2.value(b).abs(),
-            ) == target
-        });
-
-        if a.x == 0 || a.y == 0 || a.z == 0 {
-            swaps
-                .flat_map(|swap| {
-                    let mut options = Vec::new();
-                    for l in [true, false] {
-                        for j in [true, false] {
-                            for k in [true, false] {
-                                options.push((l, j, k));
-                            }
-                        }
-                    }
-                    options.into_iter().map(|neg| Rotation {
-                        swaps: *swap,
-                        negations: neg,
-                    })
-                })
-                .filter(|x| x.valid())
-                .collect()
-        } else {
-            swaps
-                .filter_map(|swap| {
-                    let negations = (
-                        a.x == -1 * swap.0.value(b),
-                        a.y == -1 * swap.1.value(b),
-                        a.z == -1 * swap.2.value(b),
-                    );
-                    Some(Rotation {
-                        swaps: *swap,
-                        negations: negations,
-                    })
-                    .filter(|x| x.valid())
-                })
-                .collect()
+    // Beacon index pairs (self, other) that might be the same beacon, most
+    // likely first. A beacon shared by both scanners has a matching distance
+    // to each of the other shared beacons, so pairs with fewer than
+    // min_votes matching distances are dropped.
+    fn anchors(
+        &self,
+        other: &Fingerprint,
+        tolerance: f64,
+        min_votes: usize,
+    ) -> Vec<(usize, usize)> {
+        let mut votes: HashMap<(usize, usize), usize> = HashMap::new();
+        for &(d, a1, a2) in self.pairs.iter() {
+            for &(_, b1, b2) in other.near(d, tolerance) {
+                for key in [(a1, b1), (a1, b2), (a2, b1), (a2, b2)] {
+                    *votes.entry(key).or_insert(0) += 1;
+                }
+            }
         }
-    }
 
-    fn apply(&self, p: &Point) -> Point {
-        let neg = |x| if x { -1 } else { 1 };
-        Point {
-            x: self.swaps.0.value(p) * neg(self.negations.0),
-            y: self.swaps.1.value(p) * neg(self.negations.1),
-            z: self.swaps.2.value(p) * neg(self.negations.2),
-        }
+        let mut ret: Vec<_> = votes.into_iter().filter(|&(_, v)| v >= min_votes).collect();
+        ret.sort_unstable_by_key(|&(k, v)| (Reverse(v), k));
+        ret.into_iter().map(|(k, _)| k).collect()
     }
+}
 
-    fn valid(&self) -> bool {
-        // all Axes must be unique
-        if self.swaps.0 == self.swaps.1
-            || self.swaps.0 == self.swaps.2
-            || self.swaps.1 == self.swaps.2
-        {
-            return false;
-        }
-
-        let num_diffs = {
-            (self.swaps.0 != Axis::X) as u8
-                + (self.swaps.1 != Axis::Y) as u8
-                + (self.swaps.2 != Axis::Z) as u8
-        };
-        let num_swaps = match num_diffs {
-            0 => 0,
-            2 => 1,
-            3 => 2,
-            _ => panic!("impossible"),
-        };
-
-        // Number of negations must be odd iff number of swaps is odd.
-        let negative_parity = num_swaps % 2;
-        let negatives =
-            { self.negations.0 as u8 + self.negations.1 as u8 + self.negations.2 as u8 };
-
-        if negatives % 2 != negative_parity {
-            return false;
-        }
-
-        true
-    }
+// The average of the vectors, rounded to the nearest integer on each axis.
+fn mean(vectors: &[Vector<3>]) -> Vector<3> {
+    let n = vectors.len() as f64;
+    Vector([0, 1, 2].map(|axis| {
+        let sum: f64 = vectors.iter().map(|v| v[axis] as f64).sum();
+        (sum / n).round() as i64
+    }))
 }
 
-impl Default for Rotation {
-    // The identity rotation.
-    fn default() -> Self {
-        Rotation {
-            swaps: (Axis::X, Axis::Y, Axis::Z),
-            negations: (false, false, false),
-        }
-    }
+// Rotations that turn b into a, allowing each axis to be off by tolerance.
+fn rotations_near(a: &Vector<3>, b: &Vector<3>, tolerance: i64) -> Vec<Rotation<3>> {
+    Rotation::all()
+        .filter(|r| (r.apply(*b) - *a).chebyshev() <= tolerance)
+        .collect()
 }
 
 // First applies a rotation, then translation
 #[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
-struct Transformation {
-    rotation: Rotation,
-    translation: PointDiff,
+pub struct Transformation {
+    rotation: Rotation<3>,
+    translation: Vector<3>,
 }
 
 impl Transformation {
-    fn apply(&self, p: &Point) -> Point {
-        self.rotation.apply(&p).translate(&self.translation)
+    fn apply(&self, p: &Point<3>) -> Point<3> {
+        self.rotation.apply_point(*p) + self.translation
     }
-}
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug, Hash)]
-enum Axis {
-    X,
-    Y,
-    Z,
-}
+    /// The transformation that applies other first, then self.
+    pub fn compose(&self, other: &Transformation) -> Transformation {
+        Transformation {
+            rotation: self.rotation.compose(&other.rotation),
+            translation: self.rotation.apply(other.translation) + self.translation,
+        }
+    }
 
-impl Axis {
-    fn value(&self, p: &Point) -> i32 {
-        match self {
-            Axis::X => p.x,
-            Axis::Y => p.y,
-            Axis::Z => p.z,
+    pub fn inverse(&self) -> Transformation {
+        let rotation = self.rotation.inverse();
+        Transformation {
+            rotation,
+            translation: -rotation.apply(self.translation),
         }
     }
 }
 
 mod parser {
-    use super::Scanner;
+    use super::{Point, Scanner};
     use crate::lib::combinators::*;
 
     pub fn parse(input: &str) -> IResult<&str, Vec<Scanner>> {
         let point = map(
             tuple((int, tag(","), int, tag(","), int)),
-            |(x, _, y, _, z)| (x, y, z).into(),
+            |(x, _, y, _, z)| Point([x, y, z]),
         );
         let points = separated_list1(line_ending, point);
         let header = delimited(tag("--- scanner "), uint, tag(" ---"));
@@ -555,21 +772,19 @@ mod tests {
     #[test]
     fn overlap_test() {
         let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
-        assert_eq!(scanners[0]._id, 0);
-        assert_eq!(scanners[1]._id, 1);
+        assert_eq!(scanners[0].id, 0);
+        assert_eq!(scanners[1].id, 1);
         let ans = scanners[0].overlap(
             &scanners[1],
-            &Point::from((-618, -824, -621)),
-            &Point::from((686, 422, 578)),
+            &Point([-618, -824, -621]),
+            &Point([686, 422, 578]),
+            &MatchConfig::default(),
         );
         assert_eq!(
             ans,
             Some(Transformation {
-                rotation: Rotation {
-                    swaps: (Axis::X, Axis::Y, Axis::Z),
-                    negations: (true, false, true)
-                },
-                translation: (68, -1246, -43).into()
+                rotation: Rotation::from_matrix([[-1, 0, 0], [0, 1, 0], [0, 0, -1]]).unwrap(),
+                translation: Vector([68, -1246, -43])
             })
         );
     }
@@ -577,32 +792,226 @@ mod tests {
     #[test]
     fn find_overlap_test() {
         let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
-        assert_eq!(scanners[0]._id, 0);
-        assert_eq!(scanners[1]._id, 1);
-        let ans = scanners[0].find_overlap(&scanners[1]);
+        assert_eq!(scanners[0].id, 0);
+        assert_eq!(scanners[1].id, 1);
+        let ans = scanners[0].find_overlap(
+            &scanners[1],
+            &MatchConfig::default(),
+            &mut MatchStats::default(),
+        );
         assert_eq!(
             ans,
-            Some(Transformation {
-                rotation: Rotation {
-                    swaps: (Axis::X, Axis::Y, Axis::Z),
-                    negations: (true, false, true)
-                },
-                translation: (68, -1246, -43).into()
-            })
+            Some((
+                12,
+                Transformation {
+                    rotation: Rotation::from_matrix([[-1, 0, 0], [0, 1, 0], [0, 0, -1]]).unwrap(),
+                    translation: Vector([68, -1246, -43])
+                }
+            ))
         );
     }
 
     #[test]
     fn rotations_to_equal_test() {
-        let a = Point::from((-618, -824, -621)).diff(&(-537, -823, -458).into());
-        let b = Point::from((686, 422, 578)).diff(&(605, 423, 415).into());
+        let a = Point([-618, -824, -621]) - Point([-537, -823, -458]);
+        let b = Point([686, 422, 578]) - Point([605, 423, 415]);
 
         assert_eq!(
-            Rotation::rotations_to_equal(&a, &b),
-            vec![Rotation {
-                swaps: (Axis::X, Axis::Y, Axis::Z),
-                negations: (true, false, true)
-            }]
+            rotations_near(&a, &b, 0),
+            vec![Rotation::from_matrix([[-1, 0, 0], [0, 1, 0], [0, 0, -1]]).unwrap()]
         );
     }
+
+    #[test]
+    fn match_stats_test() {
+        let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        assert!(
+            scanners[0]
+                .fingerprint
+                .shared(&scanners[1].fingerprint, 0.0)
+                >= 66
+        );
+
+        let solution = solve_scanners(scanners, &MatchConfig::default());
+        let stats = solution.stats;
+        assert_eq!(solution.placed.len(), 5);
+        assert_eq!(stats.matches, 4);
+        assert!(stats.anchors_tried >= stats.hypotheses);
+        assert!(stats.hypotheses >= stats.matches);
+        assert!(stats.scanner_pairs >= stats.matches + stats.fingerprint_rejects);
+    }
+
+    #[test]
+    fn min_overlap_test() {
+        // every overlap in the example is exactly 12 beacons.
+        let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let config = MatchConfig {
+            min_overlap: 13,
+            ..MatchConfig::default()
+        };
+        let solution = solve_scanners(scanners, &config);
+
+        assert_eq!(solution.placed.len(), 1);
+        let mut unplaced: Vec<_> = solution.unplaced.iter().map(|s| s.id).collect();
+        unplaced.sort();
+        assert_eq!(unplaced, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn noisy_match_test() {
+        let mut scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+
+        // nudge every beacon seen by scanners other than 0 by up to 1 per axis.
+        for s in scanners.iter_mut().skip(1) {
+            let beacons: Vec<_> = s
+                .beacons
+                .iter()
+                .enumerate()
+                .map(|(i, b)| {
+                    let i = i as i64;
+                    *b + Vector([i % 3 - 1, (i / 3) % 3 - 1, (i / 9) % 3 - 1])
+                })
+                .collect();
+            *s = Scanner::from((s.id, beacons));
+        }
+
+        let exact = solve_scanners(
+            parser::parse(EXAMPLE_INPUT).unwrap().1,
+            &MatchConfig::default(),
+        );
+        let noisy = solve_scanners(
+            scanners,
+            &MatchConfig {
+                tolerance: 2,
+                ..MatchConfig::default()
+            },
+        );
+
+        assert!(noisy.unplaced.is_empty());
+        for s in noisy.placed.iter() {
+            let expected = exact.placed.iter().find(|e| e.id == s.id).unwrap();
+            assert!(s.location.chebyshev(&expected.location) <= 3);
+        }
+    }
+
+    #[test]
+    fn relative_poses_test() {
+        let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let original: Vec<_> = scanners.iter().map(|s| s.beacons.clone()).collect();
+        let solution = solve_scanners(scanners, &MatchConfig::default());
+
+        for s in solution.placed.iter() {
+            let t = &s.pose;
+            assert_eq!(t.compose(&t.inverse()), Transformation::default());
+            assert_eq!(t.inverse().compose(t), Transformation::default());
+            let local = &original[s.id as usize];
+            let placed: Vec<_> = local.iter().map(|b| t.apply(b)).collect();
+            assert_eq!(placed, s.beacons);
+        }
+
+        let poses = solution.relative_poses(1).unwrap();
+        assert_eq!(poses.len(), 5);
+        assert_eq!(poses[1], (1, Transformation::default()));
+
+        // scanner 0 as seen from scanner 1.
+        let s1 = solution.placed.iter().find(|s| s.id == 1).unwrap();
+        assert_eq!(poses[0], (0, s1.pose.inverse()));
+        assert_eq!(poses[0].1.apply(&Point::ORIGIN), Point([68, 1246, -43]));
+
+        // chaining back through scanner 1 gives the absolute poses again.
+        for (id, rel) in poses.iter() {
+            let s = solution.placed.iter().find(|s| s.id == *id).unwrap();
+            assert_eq!(s1.pose.compose(rel), s.pose);
+        }
+
+        assert!(solution.relative_poses(9).is_none());
+
+        let report = relative_poses(EXAMPLE_INPUT, 1).unwrap();
+        let mut lines = report.lines();
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("scanner 0 at (68, 1246, -43) "));
+        assert!(lines
+            .next()
+            .unwrap()
+            .starts_with("scanner 1 at (0, 0, 0) rotation [[1, 0, 0], [0, 1, 0], [0, 0, 1]]"));
+        assert!(relative_poses(EXAMPLE_INPUT, 9).is_err());
+    }
+
+    #[test]
+    fn report_test() {
+        let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let solution = solve_scanners(scanners, &MatchConfig::default());
+
+        let report = solution.report();
+        assert_eq!(report.len(), 5);
+        assert_eq!(report[0].alignment, None);
+        assert_eq!(report[2].location, Point([1105, -1205, 1229]));
+        assert_eq!(
+            report[2].alignment,
+            Some(Alignment {
+                parent: 4,
+                overlap: 12
+            })
+        );
+
+        let tree: Vec<_> = solution
+            .alignment_tree()
+            .lines()
+            .map(|l| {
+                let indent = l.len() - l.trim_start().len();
+                let id = l.trim_start().split(' ').nth(1).unwrap().to_string();
+                (indent, id)
+            })
+            .collect();
+        let expected = [(0, "0"), (2, "1"), (4, "3"), (4, "4"), (6, "2")];
+        let expected: Vec<_> = expected
+            .iter()
+            .map(|&(i, id)| (i, id.to_string()))
+            .collect();
+        assert_eq!(tree, expected);
+    }
+
+    #[test]
+    fn export_test() {
+        let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let solution = solve_scanners(scanners, &MatchConfig::default());
+
+        let csv = solution.to_csv();
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some("kind,id,x,y,z"));
+        assert_eq!(lines.next(), Some("scanner,0,0,0,0"));
+        assert_eq!(lines.next(), Some("scanner,1,68,-1246,-43"));
+        assert_eq!(
+            csv.lines().filter(|l| l.starts_with("beacon,,")).count(),
+            79
+        );
+        assert!(csv.contains("beacon,,-892,524,684\n"));
+
+        let ply = solution.to_ply();
+        assert!(ply.starts_with("ply\nformat ascii 1.0\n"));
+        assert!(ply.contains("element vertex 84\n"));
+        let body: Vec<_> = ply.split("end_header\n").nth(1).unwrap().lines().collect();
+        assert_eq!(body.len(), 84);
+        assert_eq!(body[1], "68 -1246 -43 255 0 0");
+
+        assert_eq!(export(EXAMPLE_INPUT, "csv"), Ok(csv));
+        let tree = export(EXAMPLE_INPUT, "tree").unwrap();
+        assert!(tree.starts_with("scanner 0 at "));
+        assert!(tree.ends_with(", 4 matched\n"));
+        assert!(export(EXAMPLE_INPUT, "xyz").is_err());
+        assert!(export("--- scanner 0 ---\n1,2", "csv").is_err());
+    }
+
+    #[test]
+    fn unplaced_report_test() {
+        let scanners = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let config = MatchConfig {
+            min_overlap: 13,
+            ..MatchConfig::default()
+        };
+        let tree = solve_scanners(scanners, &config).alignment_tree();
+        assert!(tree.ends_with("unplaced: 1, 2, 3, 4\n"));
+    }
 }
@@ -1,6 +1,10 @@
+use crate::lib::ratio::Ratio;
+
 pub fn problem1(input: &str) -> String {
-    let (p1, p2) = parser::parse(input).unwrap().1;
-    let mut game = Game::new(p1, p2);
+    let starts = parser::parse(input).unwrap().1;
+    let rules = GameRules::PRACTICE;
+    rules.check_starts(&starts).unwrap();
+    let mut game = Game::new(&rules, &starts);
     while !game.is_winner() {
         game.play_turn();
     }
@@ -9,54 +13,151 @@ pub fn problem1(input: &str) -> String {
         .players
         .iter()
         .map(|p| p.score)
-        .find(|&s| s < 1000)
+        .filter(|&s| s < rules.target)
+        .min()
         .unwrap();
     let die_rolls = game.die.cur;
     let ans = non_winner_score * die_rolls;
     format!("{}", ans)
 }
 
+/// Everything about a game of Dirac Dice besides who plays and where they
+/// start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GameRules {
+    // spaces on the board, numbered from 1.
+    board: u32,
+    // score that wins the game.
+    target: u32,
+    die_sides: u32,
+    // rolls per turn, added up to move.
+    rolls: u32,
+}
+
+impl GameRules {
+    /// The practice game from part one, played with a deterministic die.
+    pub const PRACTICE: GameRules = GameRules {
+        board: 10,
+        target: 1000,
+        die_sides: 100,
+        rolls: 3,
+    };
+
+    /// The game from part two, played with the three-sided Dirac die.
+    pub const DIRAC: GameRules = GameRules {
+        board: 10,
+        target: 21,
+        die_sides: 3,
+        rolls: 3,
+    };
+
+    /// Fails if the board, the target or the die is empty.
+    pub fn new(board: u32, target: u32, die_sides: u32, rolls: u32) -> Result<Self, &'static str> {
+        if board == 0 {
+            return Err("the board needs at least one space");
+        }
+        if target == 0 {
+            return Err("the target score must be at least 1");
+        }
+        if die_sides == 0 {
+            return Err("the die needs at least one side");
+        }
+
+        Ok(GameRules {
+            board,
+            target,
+            die_sides,
+            rolls,
+        })
+    }
+
+    /// Fails unless every player starts on a space of the board.
+    pub fn check_starts(&self, starts: &[u32]) -> Result<(), &'static str> {
+        match starts.iter().all(|p| (1..=self.board).contains(p)) {
+            true => Ok(()),
+            false => Err("starting positions must be on the board"),
+        }
+    }
+
+    /// In how many of the ways to roll a turn each total comes up, as
+    /// (total, ways) pairs.
+    fn roll_totals(&self) -> Vec<(usize, u128)> {
+        let mut ways = vec![1u128];
+        for _ in 0..self.rolls {
+            let mut next = vec![0; ways.len() + self.die_sides as usize];
+            for (total, w) in ways.iter().enumerate() {
+                for side in 1..=self.die_sides as usize {
+                    next[total + side] += w;
+                }
+            }
+            ways = next;
+        }
+
+        ways.into_iter()
+            .enumerate()
+            .filter(|&(_, w)| w > 0)
+            .collect()
+    }
+
+    /// How many ways there are to roll a single turn, or None if that's
+    /// more than a u128 holds.
+    fn rolls_per_turn(&self) -> Option<u128> {
+        (self.die_sides as u128).checked_pow(self.rolls)
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 struct Game {
     die: DeterministicDie,
-    players: [PlayerState; 2],
+    players: Vec<PlayerState>,
     cur_player: usize,
+    board: u32,
+    target: u32,
+    rolls: u32,
 }
 
 impl Game {
-    fn new(p1: u32, p2: u32) -> Self {
+    fn new(rules: &GameRules, starts: &[u32]) -> Self {
         Game {
-            die: DeterministicDie::default(),
-            players: [PlayerState::new(p1), PlayerState::new(p2)],
+            die: DeterministicDie::new(rules.die_sides),
+            players: starts.iter().map(|&p| PlayerState::new(p)).collect(),
             cur_player: 0,
+            board: rules.board,
+            target: rules.target,
+            rolls: rules.rolls,
         }
     }
 
     // Returns winning player, if theree is one
     fn play_turn(&mut self) {
-        let n = self.die.roll(3);
+        let n = self.die.roll(self.rolls);
         let player = &mut self.players[self.cur_player];
-        player.forward(n);
+        player.forward(n, self.board);
         self.cur_player = (self.cur_player + 1) % self.players.len();
     }
 
     fn is_winner(&self) -> bool {
-        self.players.iter().any(|x| x.score >= 1000)
+        self.players.iter().any(|x| x.score >= self.target)
     }
 }
 
 #[derive(Clone, Debug, Default)]
 struct DeterministicDie {
+    sides: u32,
     cur: u32,
 }
 
 impl DeterministicDie {
+    fn new(sides: u32) -> Self {
+        DeterministicDie { sides, cur: 0 }
+    }
+
     fn roll(&mut self, n: u32) -> u32 {
         (0..n).map(|_| self.roll_once()).sum()
     }
 
     fn roll_once(&mut self) -> u32 {
-        let ret = self.cur % 100 + 1;
+        let ret = self.cur % self.sides + 1;
         self.cur += 1;
         ret
     }
@@ -76,30 +177,84 @@ impl PlayerState {
         }
     }
 
-    fn forward(&mut self, n: u32) {
-        self.position += n;
-        while self.position > 10 {
-            self.position -= 10;
-        }
+    fn forward(&mut self, n: u32, board: u32) {
+        self.position = (self.position + n - 1) % board + 1;
         self.score += self.position;
     }
 }
 
 pub fn problem2(input: &str) -> String {
-    let (p1, p2) = parser::parse(input).unwrap().1;
-    format!("{}", problem2_mod::worlds_player_wins(p1, p2))
+    let starts = parser::parse(input).unwrap().1;
+    let outcome = problem2_mod::play(&GameRules::DIRAC, &starts).unwrap();
+    format!("{}", outcome.wins.iter().max().unwrap())
+}
+
+/// Breaks down the Dirac dice game for the puzzle's players: how likely each
+/// is to win, how long games run, and who is favoured from every pair of
+/// starting positions. Rules are given as [board, target, die_sides, rolls],
+/// part two's by default.
+pub fn dirac_report(input: &str, rules: Option<[u32; 4]>) -> Result<String, &'static str> {
+    let starts = parser::parse(input)
+        .map_err(|_| "failed to parse starting positions")?
+        .1;
+    let rules = match rules {
+        Some([board, target, die_sides, rolls]) => GameRules::new(board, target, die_sides, rolls)?,
+        None => GameRules::DIRAC,
+    };
+    let outcome = problem2_mod::play(&rules, &starts)?;
+
+    let mut out = String::new();
+    for (i, p) in outcome.probabilities.iter().enumerate() {
+        out += &format!("player {} wins {:.4} = {}\n", i + 1, p.to_f64(), p);
+    }
+    let expected = outcome.expected_turns().ok_or(problem2_mod::TOO_MANY)?;
+    out += &format!("expected turns {:.4} = {}\n", expected.to_f64(), expected);
+
+    out += "\nturns  chance\n";
+    for (turns, p) in outcome.lengths.iter().enumerate() {
+        if *p != Ratio::ZERO {
+            out += &format!("{:>5}  {:.4}\n", turns, p.to_f64());
+        }
+    }
+
+    out += "\nplayer 1 wins, by start\n p1\\p2";
+    for p2 in 1..=rules.board {
+        out += &format!(" {:>5}", p2);
+    }
+    for (p1, row) in problem2_mod::win_table(&rules)?.iter().enumerate() {
+        out += &format!("\n{:>6}", p1 + 1);
+        for p in row {
+            out += &format!(" {:.3}", p.to_f64());
+        }
+    }
+    Ok(out + "\n")
 }
 
 mod problem2_mod {
+    use super::GameRules;
+    use crate::lib::ratio::Ratio;
+
+    pub const TOO_MANY: &str = "too many universes to count in a u128";
+
     #[derive(Clone, Debug, Default)]
     struct Turn {
         // worlds[position][score] = world_count
-        worlds: [[u64; 21]; 10],
-        wins: u64,
+        worlds: Vec<Vec<u128>>,
+        wins: u128,
+        // the chance of still playing after this turn, and of winning on it.
+        in_progress: Ratio,
+        won: Ratio,
     }
 
     impl Turn {
-        fn games_in_progress(&self) -> u64 {
+        fn new(rules: &GameRules) -> Self {
+            Turn {
+                worlds: vec![vec![0; rules.target as usize]; rules.board as usize],
+                ..Default::default()
+            }
+        }
+
+        fn games_in_progress(&self) -> u128 {
             self.worlds.iter().flat_map(|x| x.iter()).sum()
         }
 
@@ -108,76 +263,177 @@ mod problem2_mod {
         }
     }
 
-    // Number of worlds where values [3, 9] are rolled per turn.
-    const ROLL_FACTOR: [u64; 7] = [1, 3, 6, 7, 6, 3, 1];
+    /// How a game of Dirac Dice plays out over every universe.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Outcome {
+        /// The universes each player wins in.
+        pub wins: Vec<u128>,
+        /// The chance each player wins.
+        pub probabilities: Vec<Ratio>,
+        /// The chance the game ends on each turn, counting every player's
+        /// turns and starting from turn 0.
+        pub lengths: Vec<Ratio>,
+    }
+
+    impl Outcome {
+        /// None if it doesn't fit in a u128.
+        pub fn expected_turns(&self) -> Option<Ratio> {
+            self.lengths
+                .iter()
+                .enumerate()
+                .try_fold(Ratio::ZERO, |sum, (t, &p)| {
+                    sum.checked_add(Ratio::from(t as u128).checked_mul(p)?)
+                })
+        }
+    }
+
+    /// Fails when a player starts off the board, or the game runs long
+    /// enough that the universes can't be counted in a u128.
+    pub fn play(rules: &GameRules, starts: &[u32]) -> Result<Outcome, &'static str> {
+        rules.check_starts(starts)?;
+        let turns = starts
+            .iter()
+            .map(|&p| player_worlds(rules, p as usize))
+            .collect::<Result<Vec<_>, _>>()?;
+        let turns: Vec<_> = turns.iter().map(|t| t.as_slice()).collect();
+        combine(&turns)
+    }
 
-    pub fn worlds_player_wins(p1: u32, p2: u32) -> u64 {
-        let p1_turns = player_worlds(p1 as usize);
-        let p2_turns = player_worlds(p2 as usize);
+    /// The chance the first player wins a two player game, for every pair
+    /// of starting positions: table[p1 - 1][p2 - 1].
+    pub fn win_table(rules: &GameRules) -> Result<Vec<Vec<Ratio>>, &'static str> {
+        // each player's worlds only depend on where they start.
+        let worlds = (1..=rules.board as usize)
+            .map(|p| player_worlds(rules, p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        worlds
+            .iter()
+            .map(|w1| {
+                worlds
+                    .iter()
+                    .map(|w2| Ok(combine(&[w1, w2])?.probabilities[0]))
+                    .collect()
+            })
+            .collect()
+    }
 
-        let mut p1_wins = 0;
-        let mut p2_wins = 0;
+    // Plays the players' turns off against each other. Every round each
+    // player wins in the worlds they finish in, times the worlds everyone
+    // else is still playing in.
+    fn combine(turns: &[&[Turn]]) -> Result<Outcome, &'static str> {
+        let players = turns.len();
+        let rounds = turns.iter().map(|t| t.len()).max().unwrap_or(0);
+
+        // a player has moved `round` times on their turn in that round, and
+        // everyone after them `round - 1` times.
+        let in_progress = |player: usize, round: usize| {
+            turns[player]
+                .get(round)
+                .map_or((0, Ratio::ZERO), |t| (t.games_in_progress(), t.in_progress))
+        };
+
+        let mut wins = vec![0u128; players];
+        let mut probabilities = vec![Ratio::ZERO; players];
+        let mut lengths = vec![Ratio::ZERO; players * rounds];
+        for round in 1..rounds {
+            for (i, player_turns) in turns.iter().enumerate() {
+                let Some(turn) = player_turns.get(round) else {
+                    continue;
+                };
+                let mut universes = turn.wins;
+                let mut chance = turn.won;
+                for j in (0..players).filter(|&j| j != i) {
+                    let (count, p) = in_progress(j, if j < i { round } else { round - 1 });
+                    universes = universes.checked_mul(count).ok_or(TOO_MANY)?;
+                    chance = chance.checked_mul(p).ok_or(TOO_MANY)?;
+                }
 
-        let mut games_in_progress = 0;
+                let moves = players * (round - 1) + i + 1;
+                wins[i] = wins[i].checked_add(universes).ok_or(TOO_MANY)?;
+                probabilities[i] = probabilities[i].checked_add(chance).ok_or(TOO_MANY)?;
+                lengths[moves] = lengths[moves].checked_add(chance).ok_or(TOO_MANY)?;
+            }
+        }
 
-        for (t1, t2) in p1_turns.iter().zip(p2_turns.iter()) {
-            p1_wins += t1.wins * games_in_progress;
-            games_in_progress = t1.games_in_progress();
-            p2_wins += t2.wins * games_in_progress;
-            games_in_progress = t2.games_in_progress();
+        // no game lasts into the last round, everyone is done by then.
+        while lengths.last() == Some(&Ratio::ZERO) {
+            lengths.pop();
         }
 
-        p1_wins.max(p2_wins)
+        Ok(Outcome {
+            wins,
+            probabilities,
+            lengths,
+        })
     }
 
-    fn player_worlds(starting_position: usize) -> Vec<Turn> {
+    fn player_worlds(
+        rules: &GameRules,
+        starting_position: usize,
+    ) -> Result<Vec<Turn>, &'static str> {
         let mut turns = Vec::new();
 
         let mut last_turn = {
-            let mut turn = Turn::default();
-            turn.worlds[(starting_position - 1) % 10][0] = 1;
+            let mut turn = Turn::new(rules);
+            turn.worlds[(starting_position - 1) % rules.board as usize][0] = 1;
+            turn.in_progress = Ratio::ONE;
             turn
         };
         turns.push(last_turn.clone());
 
+        // no total comes up more ways than there are to roll, so checking
+        // those first keeps roll_totals from overflowing.
+        let per_turn = rules.rolls_per_turn().ok_or(TOO_MANY)?;
+        let roll_totals = rules.roll_totals();
         while !last_turn.all_worlds_ended() {
-            last_turn = next_turn(&last_turn);
+            last_turn = next_turn(rules, &roll_totals, &last_turn).ok_or(TOO_MANY)?;
+
+            // every turn so far split each universe this many ways.
+            let universes = per_turn.checked_pow(turns.len() as u32).ok_or(TOO_MANY)?;
+            last_turn.in_progress = Ratio::new(last_turn.games_in_progress(), universes);
+            last_turn.won = Ratio::new(last_turn.wins, universes);
             turns.push(last_turn.clone());
         }
 
-        turns
+        Ok(turns)
     }
 
-    fn next_turn(prev: &Turn) -> Turn {
-        let mut next = Turn::default();
+    fn next_turn(rules: &GameRules, roll_totals: &[(usize, u128)], prev: &Turn) -> Option<Turn> {
+        let mut next = Turn::new(rules);
+        let board = rules.board as usize;
+        let target = rules.target as usize;
 
-        for roll in 3..=9 {
+        for &(roll, ways) in roll_totals {
             for (pos, scores) in prev.worlds.iter().enumerate() {
-                let new_pos = (pos + roll) % 10;
+                let new_pos = (pos + roll) % board;
                 let d_score = new_pos + 1;
 
                 for (score, count) in scores.iter().enumerate() {
                     let new_score = score + d_score;
-                    if new_score >= 21 {
-                        next.wins += count * ROLL_FACTOR[roll - 3];
+                    let worlds = count.checked_mul(ways)?;
+                    if new_score >= target {
+                        next.wins = next.wins.checked_add(worlds)?;
                     } else {
-                        next.worlds[new_pos][new_score] += count * ROLL_FACTOR[roll - 3];
+                        let cell = &mut next.worlds[new_pos][new_score];
+                        *cell = cell.checked_add(worlds)?;
                     }
                 }
             }
         }
 
-        next
+        Some(next)
     }
 }
 
 mod parser {
     use crate::lib::combinators::*;
 
-    pub fn parse(input: &str) -> IResult<&str, (u32, u32)> {
-        let p1 = preceded(tag("Player 1 starting position: "), uint);
-        let p2 = preceded(tag("Player 2 starting position: "), uint);
-        let parser = separated_pair(p1, line_ending, p2);
+    // Players are listed in turn order, so their numbers aren't kept.
+    pub fn parse(input: &str) -> IResult<&str, Vec<u32>> {
+        let player = preceded(tag("Player "), uint::<u32>);
+        let start = preceded(pair(player, tag(" starting position: ")), uint);
+        let parser = separated_list1(line_ending, start);
         complete(parser)(input)
     }
 }
@@ -185,6 +441,8 @@ mod parser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::lib::ratio::Ratio;
+    use std::collections::HashMap;
 
     const EXAMPLE_INPUT: &str = "Player 1 starting position: 4
 Player 2 starting position: 8";
@@ -197,4 +455,179 @@ Player 2 starting position: 8";
     fn problem2_test() {
         assert_eq!(problem2(EXAMPLE_INPUT), "444356092776315")
     }
+
+    #[test]
+    fn rules_test() {
+        assert_eq!(
+            GameRules::DIRAC.roll_totals(),
+            vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]
+        );
+
+        // a six-sided die rolled once a turn, first to 10: the die comes up
+        // 1, 2, 3, 4, 5 and the second player wins with 3 + 8.
+        let rules = GameRules::new(10, 10, 6, 1).unwrap();
+        let mut game = Game::new(&rules, &[1, 1, 1]);
+        while !game.is_winner() {
+            game.play_turn();
+        }
+        let scores: Vec<_> = game.players.iter().map(|p| p.score).collect();
+        assert_eq!(scores, vec![8, 11, 4]);
+        assert_eq!(game.die.cur, 5);
+
+        assert!(GameRules::new(0, 21, 3, 3).is_err());
+        assert!(GameRules::new(10, 0, 3, 3).is_err());
+        assert!(GameRules::new(10, 21, 0, 3).is_err());
+        assert_eq!(GameRules::new(10, 21, 3, 3), Ok(GameRules::DIRAC));
+        assert!(rules.check_starts(&[1, 10]).is_ok());
+        assert!(rules.check_starts(&[0, 4]).is_err());
+        assert!(rules.check_starts(&[4, 11]).is_err());
+    }
+
+    // Plays every universe one roll total at a time, remembering the odds
+    // and the expected number of turns left from each state.
+    fn brute_force(rules: &GameRules, starts: &[u32]) -> (Vec<Ratio>, Ratio) {
+        type State = (Vec<(usize, usize)>, usize);
+        fn rec(
+            rules: &GameRules,
+            state: State,
+            memo: &mut HashMap<State, (Vec<Ratio>, Ratio)>,
+        ) -> (Vec<Ratio>, Ratio) {
+            if let Some(p) = memo.get(&state) {
+                return p.clone();
+            }
+
+            let (players, cur) = state.clone();
+            let mut probabilities = vec![Ratio::ZERO; players.len()];
+            let mut turns = Ratio::ZERO;
+            for (roll, ways) in rules.roll_totals() {
+                let chance = Ratio::new(ways, rules.rolls_per_turn().unwrap());
+                let mut next = players.clone();
+                let (pos, score) = &mut next[cur];
+                *pos = (*pos + roll - 1) % rules.board as usize + 1;
+                *score += *pos;
+                turns += chance;
+
+                if *score >= rules.target as usize {
+                    probabilities[cur] += chance;
+                    continue;
+                }
+                let (after, after_turns) = rec(rules, (next, (cur + 1) % players.len()), memo);
+                for (p, a) in probabilities.iter_mut().zip(after) {
+                    *p += chance * a;
+                }
+                turns += chance * after_turns;
+            }
+
+            memo.insert(state, (probabilities.clone(), turns));
+            (probabilities, turns)
+        }
+
+        let players = starts.iter().map(|&p| (p as usize, 0)).collect();
+        rec(rules, (players, 0), &mut HashMap::new())
+    }
+
+    #[test]
+    fn dirac_test() {
+        let outcome = problem2_mod::play(&GameRules::DIRAC, &[4, 8]).unwrap();
+        assert_eq!(outcome.wins, vec![444356092776315, 341960390180808]);
+        assert_eq!(
+            outcome.probabilities.iter().copied().sum::<Ratio>(),
+            Ratio::ONE
+        );
+        assert_eq!(
+            (
+                outcome.probabilities.clone(),
+                outcome.expected_turns().unwrap()
+            ),
+            brute_force(&GameRules::DIRAC, &[4, 8])
+        );
+        // nobody reaches 21 in fewer than 3 turns of their own.
+        assert_eq!(
+            outcome.lengths.iter().position(|&p| p != Ratio::ZERO),
+            Some(5)
+        );
+        assert_eq!(outcome.lengths.iter().copied().sum::<Ratio>(), Ratio::ONE);
+
+        let rules = GameRules::new(7, 12, 2, 2).unwrap();
+        let outcome = problem2_mod::play(&rules, &[1, 5, 3]).unwrap();
+        assert_eq!(
+            (
+                outcome.probabilities.clone(),
+                outcome.expected_turns().unwrap()
+            ),
+            brute_force(&rules, &[1, 5, 3])
+        );
+        assert_eq!(
+            outcome.probabilities.iter().copied().sum::<Ratio>(),
+            Ratio::ONE
+        );
+
+        let starts = parser::parse("Player 1 starting position: 1\nPlayer 2 starting position: 5\nPlayer 3 starting position: 3");
+        assert_eq!(starts.unwrap().1, vec![1, 5, 3]);
+    }
+
+    #[test]
+    fn long_game_test() {
+        // games here run to 26 turns, where a universe splits 27^26 ways.
+        let rules = GameRules::new(10, 30, 3, 3).unwrap();
+        let outcome = problem2_mod::play(&rules, &[4, 8]).unwrap();
+        assert_eq!(
+            outcome.probabilities.iter().copied().sum::<Ratio>(),
+            Ratio::ONE
+        );
+        assert_eq!(outcome.probabilities, brute_force(&rules, &[4, 8]).0);
+
+        // far past what a u128 can count.
+        let rules = GameRules::new(10, 100, 3, 3).unwrap();
+        assert_eq!(
+            problem2_mod::play(&rules, &[4, 8]),
+            Err(problem2_mod::TOO_MANY)
+        );
+
+        // 3^81 ways to roll a single turn.
+        let rules = GameRules::new(10, 100, 3, 81).unwrap();
+        assert_eq!(rules.rolls_per_turn(), None);
+        assert_eq!(
+            problem2_mod::play(&rules, &[4, 8]),
+            Err(problem2_mod::TOO_MANY)
+        );
+        assert_eq!(
+            dirac_report("Player 1 starting position: x", None),
+            Err("failed to parse starting positions")
+        );
+        assert_eq!(
+            dirac_report(
+                "Player 1 starting position: 0\nPlayer 2 starting position: 8",
+                None
+            ),
+            Err("starting positions must be on the board")
+        );
+        assert_eq!(
+            dirac_report(EXAMPLE_INPUT, Some([0, 21, 3, 3])),
+            Err("the board needs at least one space")
+        );
+        let report = dirac_report(EXAMPLE_INPUT, Some([8, 8, 2, 2])).unwrap();
+        assert!(report.contains("\n p1\\p2     1     2     3     4     5     6     7     8\n"));
+    }
+
+    #[test]
+    fn win_table_test() {
+        let rules = GameRules::new(5, 8, 2, 2).unwrap();
+        let table = problem2_mod::win_table(&rules).unwrap();
+        assert_eq!(table.len(), 5);
+        for (p1, row) in table.iter().enumerate() {
+            for (p2, &p) in row.iter().enumerate() {
+                let starts = [p1 as u32 + 1, p2 as u32 + 1];
+                assert_eq!(p, brute_force(&rules, &starts).0[0]);
+            }
+        }
+
+        let table = problem2_mod::win_table(&GameRules::DIRAC).unwrap();
+        assert_eq!(
+            table[3][7],
+            problem2_mod::play(&GameRules::DIRAC, &[4, 8])
+                .unwrap()
+                .probabilities[0]
+        );
+    }
 }
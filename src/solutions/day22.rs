@@ -1,154 +1,479 @@
-use std::fmt;
-use std::mem;
+use crate::lib::geom::Point;
+use crate::lib::interval::{IntervalBox, Range};
+use cuboid_set::{Compressed, CuboidSet, Fragments, SignedCuboids};
+use std::str::FromStr;
 
 pub fn problem1(input: &str) -> String {
     let instructions = parser::parse(input).unwrap().1;
-    let initalization_instructions: Vec<_> = instructions
-        .into_iter()
-        .filter(|i| in_region(&i.cuboid, &(-50, 51).into()))
-        .collect();
-    let res = compute_on(&initalization_instructions);
+    let ans = cubes_on_after(&instructions, &INIT_REGION, instructions.len());
 
-    let ans: usize = res.iter().map(|c| c.area()).sum();
     format!("{}", ans)
 }
 
 pub fn problem2(input: &str) -> String {
     let instructions = parser::parse(input).unwrap().1;
-    let res = compute_on(&instructions);
+    let res: SignedCuboids = compute_on(&instructions);
 
-    println!("len {}", res.len());
-    let ans: usize = res.iter().map(|c| c.area()).sum();
-    format!("{}", ans)
+    format!("{}", res.volume())
 }
 
-fn compute_on(instructions: &[Instruction]) -> Vec<Cuboid> {
-    let mut a: Vec<Cuboid> = Vec::new();
-    let mut b: Vec<Cuboid> = Vec::new();
+const INIT_REGION: Cuboid = IntervalBox {
+    ranges: [Range {
+        start: -50,
+        end: Some(51),
+    }; 3],
+};
 
-    for inst in instructions {
-        for c in a.drain(..) {
-            b.extend(c.sub(&inst.cuboid));
+/// Which CuboidSet to replay the reboot with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Fragments,
+    Signed,
+    /// A grid over the coordinates seen so far. Holds far more pieces than
+    /// the others, and is the slowest of the three on the full reboot.
+    Compressed,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fragments" => Ok(Backend::Fragments),
+            "signed" => Ok(Backend::Signed),
+            "compressed" => Ok(Backend::Compressed),
+            _ => Err(format!(
+                "unknown backend {}, try fragments, signed or compressed",
+                s
+            )),
         }
+    }
+}
 
-        if inst.state == State::On {
-            b.push(inst.cuboid);
+/// Replays the reboot step by step with the given backend, showing how many
+/// cubes are on inside the initialization region and overall, and how many
+/// pieces hold them. Given a point, also says which step last switched it and
+/// whether it ends up on.
+pub fn reactor_report(
+    input: &str,
+    point: Option<[i64; 3]>,
+    backend: Backend,
+) -> Result<String, String> {
+    let instructions = parser::parse(input).map_err(|e| e.to_string())?.1;
+    let point = point.map(Point);
+    Ok(match backend {
+        Backend::Fragments => replay::<Fragments>(&instructions, point),
+        Backend::Signed => replay::<SignedCuboids>(&instructions, point),
+        Backend::Compressed => replay::<Compressed>(&instructions, point),
+    })
+}
+
+fn replay<T: CuboidSet>(instructions: &[Instruction], point: Option<Point<3>>) -> String {
+    let everywhere = Cuboid::new([Range::unbounded(i64::MIN); 3]);
+
+    let mut out = String::from("step  init region          everywhere  pieces\n");
+    let states = states_after_each_step::<T>(instructions, &everywhere);
+    let mut last = T::default();
+    for (k, set) in states.enumerate() {
+        out += &format!(
+            "{:>4}  {:>11}  {:>18}  {:>6}\n",
+            k + 1,
+            set.volume_in(&INIT_REGION),
+            set.volume(),
+            set.fragments()
+        );
+        last = set;
+    }
+
+    if let Some(p) = point {
+        let state = if last.contains(p) { "on" } else { "off" };
+        match last_touched(instructions, p) {
+            Some(k) => out += &format!("{:?} last switched by step {}, {}\n", p, k + 1, state),
+            None => out += &format!("{:?} never switched, {}\n", p, state),
         }
+    }
+    out
+}
 
-        mem::swap(&mut a, &mut b);
+fn compute_on<T: CuboidSet>(instructions: &[Instruction]) -> T {
+    let mut set = T::default();
+
+    for inst in instructions {
+        set.apply(inst);
     }
 
-    a
+    set
 }
 
-fn in_region(c: &Cuboid, region: &Range) -> bool {
-    let in_range = |r: &Range| r.end >= region.start && r.start <= region.end;
-    in_range(&c.x) && in_range(&c.y) && in_range(&c.z)
+// Number of cubes inside region that are on after the first steps instructions,
+// or after all of them if there are fewer.
+fn cubes_on_after(instructions: &[Instruction], region: &Cuboid, steps: usize) -> usize {
+    let clipped: Vec<_> = instructions[..steps.min(instructions.len())]
+        .iter()
+        .filter_map(|i| i.clip(region))
+        .collect();
+    let res: Fragments = compute_on(&clipped);
+    res.volume()
 }
 
-// Range is semi-inclusive: [start, end)
-#[derive(Clone, Copy, Default, PartialEq, Eq)]
-struct Range {
-    start: i64,
-    end: i64,
+// Yields the cubes inside region that are on after each instruction, as a copy
+// of the set that can still answer queries about that step.
+fn states_after_each_step<'a, T: CuboidSet + 'a>(
+    instructions: &'a [Instruction],
+    region: &'a Cuboid,
+) -> impl Iterator<Item = T> + 'a {
+    let mut set = T::default();
+    instructions.iter().map(move |inst| {
+        if let Some(inst) = inst.clip(region) {
+            set.apply(&inst);
+        }
+        set.clone()
+    })
 }
 
-impl Range {
-    // Returns parts of self that are below, overlap, and above other.
-    fn split(&self, other: &Range) -> (Range, Range, Range) {
-        let low = (self.start, other.start).into();
-        let overlap = (self.start.max(other.start), self.end.min(other.end)).into();
-        let high = (other.end, self.end).into();
-        (low, overlap, high)
+// The index of the last instruction whose cuboid contains p, if any.
+fn last_touched(instructions: &[Instruction], p: Point<3>) -> Option<usize> {
+    instructions.iter().rposition(|i| i.cuboid.contains(p.0))
+}
+
+mod cuboid_set {
+    use super::*;
+    use std::collections::HashMap;
+    use std::mem;
+
+    /// A set of integer points built up by adding and removing cuboids.
+    pub trait CuboidSet: Clone + Default {
+        fn union(&mut self, c: &Cuboid);
+        fn difference(&mut self, c: &Cuboid);
+        /// Drops every point outside c.
+        fn intersection(&mut self, c: &Cuboid);
+        fn volume(&self) -> usize;
+        fn contains(&self, p: Point<3>) -> bool;
+        /// Number of pieces the backend is holding to represent the set.
+        fn fragments(&self) -> usize;
+
+        /// Number of points in the set that are also in region.
+        fn volume_in(&self, region: &Cuboid) -> usize {
+            let mut s = self.clone();
+            s.intersection(region);
+            s.volume()
+        }
+
+        fn apply(&mut self, inst: &Instruction) {
+            match inst.state {
+                State::On => self.union(&inst.cuboid),
+                State::Off => self.difference(&inst.cuboid),
+            }
+        }
     }
 
-    fn len(&self) -> usize {
-        (self.end - self.start).max(0) as usize
+    /// Keeps the set as disjoint cuboids, carving up existing ones whenever a
+    /// new cuboid overlaps them.
+    #[derive(Clone, Debug, Default)]
+    pub struct Fragments {
+        cuboids: Vec<Cuboid>,
     }
-}
 
-impl From<(i64, i64)> for Range {
-    fn from(r: (i64, i64)) -> Self {
-        Range {
-            start: r.0,
-            end: r.1,
+    impl CuboidSet for Fragments {
+        fn union(&mut self, c: &Cuboid) {
+            self.difference(c);
+            self.cuboids.push(*c);
+        }
+
+        fn difference(&mut self, c: &Cuboid) {
+            let old = mem::take(&mut self.cuboids);
+            for o in old {
+                self.cuboids.extend(o.difference(c));
+            }
+        }
+
+        fn intersection(&mut self, c: &Cuboid) {
+            let old = mem::take(&mut self.cuboids);
+            self.cuboids = old.iter().filter_map(|o| o.intersection(c)).collect();
+        }
+
+        fn volume(&self) -> usize {
+            self.cuboids.iter().map(volume).sum()
+        }
+
+        fn contains(&self, p: Point<3>) -> bool {
+            self.cuboids.iter().any(|c| c.contains(p.0))
+        }
+
+        fn fragments(&self) -> usize {
+            self.cuboids.len()
         }
     }
-}
 
-impl fmt::Debug for Range {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}..{})", self.start, self.end)
+    /// Inclusion-exclusion over signed cuboids: every overlap with a new
+    /// cuboid is cancelled out by adding it back with the opposite sign.
+    #[derive(Clone, Debug, Default)]
+    pub struct SignedCuboids {
+        counts: HashMap<Cuboid, i64>,
     }
-}
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
-struct Cuboid {
-    x: Range,
-    y: Range,
-    z: Range,
-}
+    impl SignedCuboids {
+        fn cancel_overlaps(&mut self, c: &Cuboid) {
+            let mut changes: Vec<(Cuboid, i64)> = Vec::new();
+            for (o, &n) in self.counts.iter() {
+                if let Some(i) = o.intersection(c) {
+                    changes.push((i, -n));
+                }
+            }
+            self.apply(changes);
+        }
 
-impl Cuboid {
-    fn sub(&self, other: &Cuboid) -> Vec<Cuboid> {
-        let (x_b, x_o, x_a) = self.x.split(&other.x);
-        let (y_b, y_o, y_a) = self.y.split(&other.y);
-        let (z_b, z_o, z_a) = self.z.split(&other.z);
+        fn apply(&mut self, changes: Vec<(Cuboid, i64)>) {
+            for (c, n) in changes {
+                *self.counts.entry(c).or_insert(0) += n;
+            }
+            self.counts.retain(|_, n| *n != 0);
+        }
+    }
 
-        if x_o.len() == 0 || y_o.len() == 0 || z_o.len() == 0 {
-            return vec![self.clone()];
+    impl CuboidSet for SignedCuboids {
+        fn union(&mut self, c: &Cuboid) {
+            self.cancel_overlaps(c);
+            self.apply(vec![(*c, 1)]);
         }
 
-        let mut ret = Vec::new();
+        fn difference(&mut self, c: &Cuboid) {
+            self.cancel_overlaps(c);
+        }
 
-        if y_b.len() > 0 {
-            ret.push((self.x, y_b, z_o).into());
+        fn intersection(&mut self, c: &Cuboid) {
+            let old = mem::take(&mut self.counts);
+            let changes = old
+                .into_iter()
+                .filter_map(|(o, n)| Some((o.intersection(c)?, n)))
+                .collect();
+            self.apply(changes);
         }
 
-        if y_a.len() > 0 {
-            ret.push((self.x, y_a, z_o).into());
+        fn volume(&self) -> usize {
+            let v: i64 = self.counts.iter().map(|(c, n)| volume(c) as i64 * n).sum();
+            v as usize
         }
 
-        if x_b.len() > 0 {
-            ret.push((x_b, y_o, z_o).into());
+        fn contains(&self, p: Point<3>) -> bool {
+            let n: i64 = self
+                .counts
+                .iter()
+                .filter(|(c, _)| c.contains(p.0))
+                .map(|(_, n)| n)
+                .sum();
+            n > 0
         }
 
-        if x_a.len() > 0 {
-            ret.push((x_a, y_o, z_o).into());
+        fn fragments(&self) -> usize {
+            self.counts.len()
         }
+    }
+
+    /// A grid compressed to the coordinates the operations have used so far,
+    /// updated as each one is applied. Every x slab keeps its own compressed
+    /// y axis and every row its own z axis, so a new cut only copies the slab
+    /// or row it falls in, and neighbours that end up equal are merged back.
+    #[derive(Clone, Debug, Default)]
+    pub struct Compressed {
+        grid: Axis<Axis<Axis<bool>>>,
+    }
 
-        if z_b.len() > 0 {
-            ret.push((self.x, self.y, z_b).into());
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum Op {
+        Union,
+        Difference,
+        Intersection,
+    }
+
+    // One level of the grid, with the layers for the remaining axes below it.
+    trait Layer: Clone + Default + PartialEq {
+        // Applies op inside ranges, which has one range per remaining axis.
+        fn update(&mut self, op: Op, ranges: &[Range<i64>]);
+        fn volume(&self) -> u64;
+        fn contains(&self, p: &[i64]) -> bool;
+        fn pieces(&self) -> usize;
+    }
+
+    impl Layer for bool {
+        fn update(&mut self, op: Op, _: &[Range<i64>]) {
+            match op {
+                Op::Union => *self = true,
+                Op::Difference => *self = false,
+                Op::Intersection => (),
+            }
         }
 
-        if z_a.len() > 0 {
-            ret.push((self.x, self.y, z_a).into());
+        fn volume(&self) -> u64 {
+            u64::from(*self)
         }
 
-        ret
+        fn contains(&self, _: &[i64]) -> bool {
+            *self
+        }
+
+        fn pieces(&self) -> usize {
+            usize::from(*self)
+        }
     }
 
-    fn area(&self) -> usize {
-        self.x.len() * self.y.len() * self.z.len()
+    // cells[i] covers [cuts[i], cuts[i + 1]) and everything outside the cuts
+    // is empty. The end cells are never empty and no two neighbours are equal.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    struct Axis<T> {
+        cuts: Vec<i64>,
+        cells: Vec<T>,
+    }
+
+    impl<T: Layer> Axis<T> {
+        // Makes c a cut, copying the cell it falls in, and returns its index.
+        fn split(&mut self, c: i64) -> usize {
+            let i = match self.cuts.binary_search(&c) {
+                Ok(i) => return i,
+                Err(i) => i,
+            };
+
+            // a cut outside the others opens an empty cell up to the nearest.
+            if !self.cuts.is_empty() {
+                let inside = i > 0 && i < self.cuts.len();
+                let cell = if inside {
+                    self.cells[i - 1].clone()
+                } else {
+                    T::default()
+                };
+                self.cells.insert(i.min(self.cells.len()), cell);
+            }
+            self.cuts.insert(i, c);
+            i
+        }
+
+        fn merge(&mut self) {
+            let empty = T::default();
+            let mut cuts = Vec::with_capacity(self.cuts.len());
+            let mut cells: Vec<T> = Vec::with_capacity(self.cells.len());
+            for (&cut, cell) in self.cuts.iter().zip(mem::take(&mut self.cells)) {
+                match cells.last() {
+                    Some(last) if *last == cell => (),
+                    None if cell == empty => (),
+                    _ => {
+                        cuts.push(cut);
+                        cells.push(cell);
+                    }
+                }
+            }
+
+            if let Some(&end) = self.cuts.last() {
+                cuts.push(end);
+            }
+
+            // dropping a trailing empty cell leaves its start closing the one
+            // before it.
+            while cells.last() == Some(&empty) {
+                cells.pop();
+                cuts.pop();
+            }
+            if cells.is_empty() {
+                cuts.clear();
+            }
+
+            self.cuts = cuts;
+            self.cells = cells;
+        }
+    }
+
+    impl<T: Layer> Layer for Axis<T> {
+        fn update(&mut self, op: Op, ranges: &[Range<i64>]) {
+            // cuboids always come from bounded ranges.
+            let (start, end) = (ranges[0].start, ranges[0].end.unwrap());
+            if start >= end {
+                if op == Op::Intersection {
+                    *self = Axis::default();
+                }
+                return;
+            }
+
+            let i = self.split(start);
+            let j = self.split(end);
+            for cell in self.cells[i..j].iter_mut() {
+                cell.update(op, &ranges[1..]);
+            }
+            if op == Op::Intersection {
+                self.cells.truncate(j);
+                self.cells.drain(..i);
+                self.cuts.truncate(j + 1);
+                self.cuts.drain(..i);
+            }
+            self.merge();
+        }
+
+        fn volume(&self) -> u64 {
+            let widths = self.cuts.windows(2).map(|w| (w[1] - w[0]) as u64);
+            widths.zip(&self.cells).map(|(w, c)| w * c.volume()).sum()
+        }
+
+        fn contains(&self, p: &[i64]) -> bool {
+            match self.cuts.partition_point(|&c| c <= p[0]) {
+                0 => false,
+                k if k == self.cuts.len() => false,
+                k => self.cells[k - 1].contains(&p[1..]),
+            }
+        }
+
+        fn pieces(&self) -> usize {
+            self.cells.iter().map(|c| c.pieces()).sum()
+        }
     }
-}
 
-impl From<(Range, Range, Range)> for Cuboid {
-    fn from(c: (Range, Range, Range)) -> Self {
-        Cuboid {
-            x: c.0,
-            y: c.1,
-            z: c.2,
+    impl CuboidSet for Compressed {
+        fn union(&mut self, c: &Cuboid) {
+            self.grid.update(Op::Union, &c.ranges);
+        }
+
+        fn difference(&mut self, c: &Cuboid) {
+            self.grid.update(Op::Difference, &c.ranges);
+        }
+
+        fn intersection(&mut self, c: &Cuboid) {
+            self.grid.update(Op::Intersection, &c.ranges);
+        }
+
+        fn volume(&self) -> usize {
+            self.grid.volume() as usize
+        }
+
+        fn contains(&self, p: Point<3>) -> bool {
+            self.grid.contains(&p.0)
+        }
+
+        fn fragments(&self) -> usize {
+            self.grid.pieces()
         }
     }
 }
 
+type Cuboid = IntervalBox<i64, 3>;
+
+// Cuboids always come from bounded ranges.
+fn volume(c: &Cuboid) -> usize {
+    c.volume().unwrap() as usize
+}
+
 #[derive(Clone, Debug)]
 pub struct Instruction {
     state: State,
     cuboid: Cuboid,
 }
 
+impl Instruction {
+    // The part of the instruction that falls inside region.
+    fn clip(&self, region: &Cuboid) -> Option<Instruction> {
+        Some(Instruction {
+            state: self.state,
+            cuboid: self.cuboid.intersection(region)?,
+        })
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum State {
     On,
@@ -156,8 +481,9 @@ pub enum State {
 }
 
 mod parser {
-    use super::{Instruction, State};
+    use super::{Cuboid, Instruction, State};
     use crate::lib::combinators::*;
+    use crate::lib::interval::Range;
 
     pub fn parse(input: &str) -> IResult<&str, Vec<Instruction>> {
         let on = map(tag("on"), |_| State::On);
@@ -166,12 +492,12 @@ mod parser {
         let range = || {
             map(
                 separated_pair(int::<i64>, tag(".."), int::<i64>),
-                |(s, e)| (s, e + 1).into(),
+                |(s, e)| Range::inclusive(s, e),
             )
         };
         let cuboid = map(
             tuple((tag("x="), range(), tag(",y="), range(), tag(",z="), range())),
-            |(_, x, _, y, _, z)| (x, y, z).into(),
+            |(_, x, _, y, _, z)| Cuboid::new([x, y, z]),
         );
         let instruction = map(separated_pair(state, tag(" "), cuboid), |(s, c)| {
             Instruction {
@@ -257,4 +583,190 @@ off x=-93533..-4276,y=-16170..68771,z=-104985..-24507";
     fn problem2_test() {
         assert_eq!(problem2(EXAMPLE_INPUT), "2758514936282235")
     }
+
+    #[test]
+    fn backends_agree_test() {
+        let instructions = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let region = Cuboid::new([Range::half_open(-50, 51); 3]);
+        let small: Vec<_> = instructions
+            .iter()
+            .filter_map(|i| i.clip(&region))
+            .collect();
+
+        let fragments: Fragments = compute_on(&small);
+        let signed: SignedCuboids = compute_on(&small);
+        let compressed: Compressed = compute_on(&small);
+        assert_eq!(fragments.volume(), 474140);
+        assert_eq!(signed.volume(), 474140);
+        assert_eq!(compressed.volume(), 474140);
+
+        for p in [
+            [0, 0, 0],
+            [-50, -50, -50],
+            [10, 10, 10],
+            [30, 45, 0],
+            [40, -40, -5],
+        ]
+        .map(Point)
+        {
+            assert_eq!(signed.contains(p), fragments.contains(p));
+            assert_eq!(compressed.contains(p), fragments.contains(p));
+        }
+
+        let signed: SignedCuboids = compute_on(&instructions);
+        let compressed: Compressed = compute_on(&instructions);
+        assert_eq!(signed.volume(), 2758514936282235);
+        assert_eq!(compressed.volume(), 2758514936282235);
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release"]
+    fn backend_benchmark() {
+        use std::time::{Duration, Instant};
+
+        fn time<T: CuboidSet>(instructions: &[Instruction]) -> (usize, usize, Duration) {
+            let start = Instant::now();
+            let set: T = compute_on(instructions);
+            (set.volume(), set.fragments(), start.elapsed())
+        }
+
+        let instructions = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        for steps in [20, instructions.len()] {
+            let steps = &instructions[..steps];
+            let (volume, pieces, fragments) = time::<Fragments>(steps);
+            println!(
+                "{} steps: fragments {:?} ({} pieces)",
+                steps.len(),
+                fragments,
+                pieces
+            );
+            let (v, pieces, signed) = time::<SignedCuboids>(steps);
+            println!(
+                "{} steps: signed {:?} ({} pieces)",
+                steps.len(),
+                signed,
+                pieces
+            );
+            assert_eq!(v, volume);
+            let (v, pieces, compressed) = time::<Compressed>(steps);
+            println!(
+                "{} steps: compressed {:?} ({} pieces)",
+                steps.len(),
+                compressed,
+                pieces
+            );
+            assert_eq!(v, volume);
+        }
+    }
+
+    #[test]
+    fn set_operations_test() {
+        fn check<T: CuboidSet>() {
+            let a = Cuboid::new([Range::half_open(0, 10); 3]);
+            let b = Cuboid::new([Range::half_open(5, 15); 3]);
+            let hole = Cuboid::new([Range::half_open(2, 3); 3]);
+
+            let mut set = T::default();
+            set.union(&a);
+            set.union(&b);
+            assert_eq!(set.volume(), 2000 - 125);
+            assert!(set.fragments() >= 2);
+
+            set.difference(&hole);
+            assert_eq!(set.volume(), 2000 - 125 - 1);
+            assert!(!set.contains(Point([2, 2, 2])));
+            assert!(set.contains(Point([3, 3, 3])));
+
+            set.intersection(&b);
+            assert_eq!(set.volume(), 1000);
+            assert!(!set.contains(Point([3, 3, 3])));
+            assert!(set.contains(Point([14, 14, 14])));
+        }
+
+        check::<Fragments>();
+        check::<SignedCuboids>();
+        check::<Compressed>();
+
+        // the grid merges cells back together once they agree again.
+        let a = Cuboid::new([Range::half_open(0, 10); 3]);
+        let b = Cuboid::new([Range::half_open(5, 15); 3]);
+        let mut set = Compressed::default();
+        set.union(&a);
+        set.union(&b);
+        set.difference(&b);
+        assert_eq!(set.fragments(), 3);
+        set.union(&b);
+        set.difference(&a);
+        set.difference(&b);
+        assert_eq!((set.volume(), set.fragments()), (0, 0));
+    }
+
+    const SMALL_EXAMPLE_INPUT: &str = "on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10";
+
+    #[test]
+    fn cubes_on_each_step_test() {
+        let instructions = parser::parse(SMALL_EXAMPLE_INPUT).unwrap().1;
+        let region = Cuboid::new([Range::half_open(-50, 51); 3]);
+
+        let states: Vec<Fragments> = states_after_each_step(&instructions, &region).collect();
+        let steps: Vec<_> = states.iter().map(|s| s.volume()).collect();
+        assert_eq!(steps, vec![27, 46, 38, 39]);
+
+        for (k, &expected) in steps.iter().enumerate() {
+            assert_eq!(cubes_on_after(&instructions, &region, k + 1), expected);
+        }
+        assert_eq!(cubes_on_after(&instructions, &region, 0), 0);
+        assert_eq!(cubes_on_after(&instructions, &region, 10), 39);
+
+        // at x=12 both cubes overlap, at x=13 only the second one is left.
+        let slab = Cuboid::new([
+            Range::half_open(12, 14),
+            Range::half_open(0, 20),
+            Range::half_open(0, 20),
+        ]);
+        assert_eq!(cubes_on_after(&instructions, &slab, 2), (9 + 9 - 4) + 9);
+        assert_eq!(states[1].volume_in(&slab), (9 + 9 - 4) + 9);
+        assert!(states[1].contains(Point([13, 13, 13])));
+        assert!(!states[2].contains(Point([10, 10, 10])));
+    }
+
+    #[test]
+    fn straddling_region_test() {
+        let instructions = parser::parse("on x=40..60,y=0..0,z=0..0").unwrap().1;
+        assert_eq!(problem1("on x=40..60,y=0..0,z=0..0"), "11");
+
+        let region = Cuboid::new([Range::half_open(-50, 51); 3]);
+        assert_eq!(cubes_on_after(&instructions, &region, 1), 11);
+    }
+
+    #[test]
+    fn last_touched_test() {
+        let instructions = parser::parse(SMALL_EXAMPLE_INPUT).unwrap().1;
+        assert_eq!(last_touched(&instructions, Point([10, 10, 10])), Some(3));
+        assert_eq!(last_touched(&instructions, Point([13, 13, 13])), Some(1));
+        assert_eq!(last_touched(&instructions, Point([9, 9, 9])), Some(2));
+        assert_eq!(last_touched(&instructions, Point([0, 0, 0])), None);
+
+        let report = reactor_report(SMALL_EXAMPLE_INPUT, Some([10, 10, 10]), Backend::Signed);
+        let lines: Vec<_> = report.as_ref().unwrap().lines().collect();
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[4], "   4           39                  39       5");
+        assert_eq!(lines[5], "(10, 10, 10) last switched by step 4, on");
+        assert!(reactor_report("on x=1", None, Backend::Signed).is_err());
+
+        // the backends only differ in how many pieces they hold.
+        assert_eq!("compressed".parse(), Ok(Backend::Compressed));
+        assert!("octree".parse::<Backend>().is_err());
+        for backend in [Backend::Fragments, Backend::Compressed] {
+            let other = reactor_report(SMALL_EXAMPLE_INPUT, Some([10, 10, 10]), backend).unwrap();
+            let other: Vec<_> = other.lines().collect();
+            assert_eq!(other[5], lines[5]);
+            for (a, b) in other.iter().zip(&lines).take(5) {
+                assert_eq!(&a[..a.len() - 6], &b[..b.len() - 6]);
+            }
+        }
+    }
 }
@@ -1,100 +1,171 @@
 use std::cmp::Ordering;
-use std::collections::BinaryHeap;
+use std::collections::hash_map::Entry;
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
 
 use crate::lib::heapentry::MinHeapEntry;
 
 pub fn problem1(input: &str) -> String {
-    let data = parser::parse(input).unwrap().1;
-    let burrow = Burrow::new(data);
-    let ans = bfs(burrow).unwrap();
-    format!("{}", ans)
+    let (_, energy, _) = organize(input, false).unwrap();
+    format!("{}", energy)
 }
 
 pub fn problem2(input: &str) -> String {
-    let mut lines: Vec<_> = input.lines().collect();
-    lines.splice(3..3, ["#D#C#B#A#", "#D#B#A#C#"]);
-    let spliced_input = lines.iter().fold(String::new(), |mut acc, x| {
-        acc.push_str(x);
-        acc.push('\n');
-        acc
-    });
-    let data = parser::parse(&spliced_input).unwrap().1;
-    let burrow = Burrow::new(data);
-    let ans = bfs(burrow).unwrap();
-    format!("{}", ans)
-}
-
-fn bfs(initial: Burrow) -> Option<usize> {
-    let mut frontier = BinaryHeap::new();
-    frontier.push(MinHeapEntry::new(0, initial));
+    let (_, energy, _) = organize(input, true).unwrap();
+    format!("{}", energy)
+}
 
-    while let Some(MinHeapEntry {
-        key: mut cost,
-        value: mut state,
-    }) = frontier.pop()
-    {
-        if state.is_complete() {
-            return Some(cost);
+// The rows part two unfolds below the top of the puzzle's four rooms.
+const FOLDED: [&str; 2] = ["#D#C#B#A#", "#D#B#A#C#"];
+
+// Returns the burrow as parsed, along with the least energy it takes to
+// organize and the moves that do it.
+fn organize(input: &str, unfold: bool) -> Result<(Diagram, usize, Vec<Move>), String> {
+    let mut diagram = parser::parse(input).map_err(|e| e.to_string())?.1;
+    if unfold {
+        diagram.unfold(&FOLDED)?;
+    }
+    // a room only takes its own kind, so each kind has to fill it exactly.
+    for (kind, room) in diagram.rooms.iter().enumerate() {
+        let count = diagram
+            .rooms
+            .iter()
+            .flatten()
+            .filter(|a| a.0 as usize == kind);
+        if count.count() != room.len() {
+            return Err("every kind needs as many amphipods as its room holds".to_string());
         }
+    }
+    let burrow = Burrow::new(diagram.clone());
+    let solution = search(burrow, Options::default()).ok_or("no way to organize the burrow")?;
+    Ok((diagram, solution.cost, solution.moves))
+}
 
-        // Attempt to move any possible to siderooms.
-        loop {
-            let mut moved = false;
+// The moves that led to a state, newest first. Shared between states so
+// pushing one onto the frontier doesn't copy the whole list.
+struct Trail {
+    last: Move,
+    before: Option<Rc<Trail>>,
+}
 
-            // Move from hall to sideroom
-            let hall_occupants: Vec<_> = state
-                .hall
-                .iter()
-                .enumerate()
-                .filter_map(|(i, &a)| Some((i, a?)))
-                .collect();
+fn extend(trail: &Option<Rc<Trail>>, last: Move) -> Option<Rc<Trail>> {
+    let before = trail.clone();
+    Some(Rc::new(Trail { last, before }))
+}
 
-            for (i, a) in hall_occupants {
-                if let Some(c) = state.move_to_room(a, i) {
-                    moved = true;
-                    cost += c;
-                    state.hall[i] = None;
-                }
-            }
+/// Which of the search's shortcuts to take. Turning both off searches the
+/// way it used to, which is handy for comparing stats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Options {
+    // order the frontier by cost plus a lower bound on the energy left.
+    pub heuristic: bool,
+    // skip states already reached for less.
+    pub dedup: bool,
+}
 
-            // move from sideroom to sideroom
-            let top_occupants: Vec<_> = state
-                .iter_rooms()
-                .filter_map(|(room_id, room)| Some((room_id, room.peak()?)))
-                .collect();
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            heuristic: true,
+            dedup: true,
+        }
+    }
+}
 
-            for (room_id, (leave_room_cost, a)) in top_occupants {
-                if let Some(to_room_cost) = state.move_to_room(a, room_id.room_entrance()) {
-                    state
-                        .get_room_mut(room_id)
-                        .pop()
-                        .expect("peak worked so pop should also");
-                    cost += leave_room_cost + to_room_cost;
-                    moved = true;
-                }
-            }
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub expanded: usize,
+    pub pushed: usize,
+}
 
-            if !moved {
-                break;
-            }
-        }
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Solution {
+    pub cost: usize,
+    pub moves: Vec<Move>,
+    pub stats: Stats,
+}
 
+/// How the search fares with each combination of options, one line each.
+pub fn compare_searches(input: &str, unfold: bool) -> Result<String, String> {
+    let (diagram, _, _) = organize(input, unfold)?;
+    let mut out = String::from("heuristic  dedup  expanded    pushed  energy\n");
+
+    for (heuristic, dedup) in [(false, false), (false, true), (true, false), (true, true)] {
+        let options = Options { heuristic, dedup };
+        let solution = search(Burrow::new(diagram.clone()), options).unwrap();
+        let yes_no = |b| if b { "yes" } else { "no" };
+        out.push_str(&format!(
+            "{:<9}  {:<5}  {:>8}  {:>8}  {:>6}\n",
+            yes_no(heuristic),
+            yes_no(dedup),
+            solution.stats.expanded,
+            solution.stats.pushed,
+            solution.cost
+        ));
+    }
+
+    Ok(out)
+}
+
+// Finds the least energy to organize the burrow and the moves that do it.
+fn search(initial: Burrow, options: Options) -> Option<Solution> {
+    let estimate = |s: &Burrow| if options.heuristic { s.heuristic() } else { 0 };
+    let mut stats = Stats::default();
+    let mut best = HashMap::new();
+    let mut frontier = BinaryHeap::new();
+
+    let (mut state, mut trail) = (initial, None);
+    let cost = settle(&mut state, &mut trail);
+    best.insert(state.key(), cost);
+    frontier.push(MinHeapEntry::new(
+        cost + estimate(&state),
+        (cost, state, trail),
+    ));
+
+    while let Some(MinHeapEntry {
+        value: (cost, state, trail),
+        ..
+    }) = frontier.pop()
+    {
         if state.is_complete() {
-            frontier.push(MinHeapEntry::new(cost, state));
+            let moves = trail_moves(&trail);
+            return Some(Solution { cost, moves, stats });
+        }
+
+        // a cheaper way here turned up after this one was queued.
+        if options.dedup && best.get(&state.key()).is_some_and(|&b| b < cost) {
             continue;
         }
+        stats.expanded += 1;
 
         // move every top occupant to every possible hall location.
         for i in 0..state.rooms.len() {
             let mut pop_state = state.clone();
-            let Some((to_hall_cost, a)) = pop_state.rooms[i].pop() else {continue;};
-            let entrance = pop_state.rooms[i].wanted.room_entrance();
+            let Some((to_hall_steps, a)) = pop_state.rooms[i].pop() else {continue;};
+            let entrance = pop_state.entrance(pop_state.rooms[i].wanted);
             let open_hall_locs = pop_state.open_hall_range(entrance);
             for hall_loc in open_hall_locs {
-                let hall_cost = hall_loc.abs_diff(entrance) * a.energy();
+                let steps = to_hall_steps + hall_loc.abs_diff(entrance);
                 let mut s = pop_state.clone();
                 s.hall[hall_loc] = Some(a);
-                frontier.push(MinHeapEntry::new(cost + to_hall_cost + hall_cost, s));
+
+                let energy = steps * s.energy(a);
+                let from = Location::Room(pop_state.rooms[i].wanted, to_hall_steps - 1);
+                let mut t = extend(&trail, Move::new(a, from, Location::Hall(hall_loc), energy));
+                let cost = cost + energy + settle(&mut s, &mut t);
+
+                if options.dedup {
+                    match best.entry(s.key()) {
+                        Entry::Occupied(e) if *e.get() <= cost => continue,
+                        e => *e.or_default() = cost,
+                    }
+                }
+                stats.pushed += 1;
+                frontier.push(MinHeapEntry::new(cost + estimate(&s), (cost, s, t)));
             }
         }
     }
@@ -102,7 +173,331 @@ fn bfs(initial: Burrow) -> Option<usize> {
     None
 }
 
-const HALLWAY_LENGTH: usize = 11;
+// Moves everything that can go straight into its room there, which never
+// costs more than doing it later. Returns the energy used.
+fn settle(state: &mut Burrow, trail: &mut Option<Rc<Trail>>) -> usize {
+    let mut cost = 0;
+
+    loop {
+        let mut moved = false;
+
+        // Move from hall to sideroom
+        let hall_occupants: Vec<_> = state
+            .hall
+            .iter()
+            .enumerate()
+            .filter_map(|(i, &a)| Some((i, a?)))
+            .collect();
+
+        for (i, a) in hall_occupants {
+            if let Some((energy, to)) = state.move_to_room(a, i) {
+                moved = true;
+                cost += energy;
+                state.hall[i] = None;
+                *trail = extend(trail, Move::new(a, Location::Hall(i), to, energy));
+            }
+        }
+
+        // move from sideroom to sideroom
+        let top_occupants: Vec<_> = state
+            .iter_rooms()
+            .filter_map(|(room_id, room)| Some((room_id, room.peak()?)))
+            .collect();
+
+        for (room_id, (leave_room_steps, a)) in top_occupants {
+            if let Some((to_room_cost, to)) = state.move_to_room(a, state.entrance(room_id)) {
+                state
+                    .get_room_mut(room_id)
+                    .pop()
+                    .expect("peak worked so pop should also");
+                let energy = leave_room_steps * state.energy(a) + to_room_cost;
+                cost += energy;
+                moved = true;
+
+                let from = Location::Room(room_id, leave_room_steps - 1);
+                *trail = extend(trail, Move::new(a, from, to, energy));
+            }
+        }
+
+        if !moved {
+            return cost;
+        }
+    }
+}
+
+fn trail_moves(trail: &Option<Rc<Trail>>) -> Vec<Move> {
+    let mut moves = Vec::new();
+    let mut t = trail;
+    while let Some(step) = t {
+        moves.push(step.last);
+        t = &step.before;
+    }
+    moves.reverse();
+    moves
+}
+
+/// Everything about a burrow that stays put while the amphipods move. Kind i
+/// of amphipod belongs in room i.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Layout {
+    pub hallway: usize,
+    // the hallway cell outside each room.
+    pub entrances: Vec<usize>,
+    // energy spent per step by each kind.
+    pub energy: Vec<usize>,
+}
+
+impl Layout {
+    /// Uses the puzzle's costs, where every kind spends ten times as much as
+    /// the one before it.
+    pub fn new(hallway: usize, entrances: Vec<usize>) -> Result<Self, &'static str> {
+        let energy = (0..entrances.len() as u32)
+            .map(|i| 10usize.checked_pow(i))
+            .collect::<Option<_>>()
+            .ok_or("too many kinds for the puzzle's energy costs")?;
+        Self::with_energy(hallway, entrances, energy)
+    }
+
+    /// Uses the given energy per step for each kind, in order.
+    pub fn with_energy(
+        hallway: usize,
+        entrances: Vec<usize>,
+        energy: Vec<usize>,
+    ) -> Result<Self, &'static str> {
+        if energy.len() != entrances.len() {
+            return Err("need an energy cost for every kind");
+        }
+        Ok(Self {
+            hallway,
+            entrances,
+            energy,
+        })
+    }
+}
+
+/// A burrow as drawn in the input: its layout, and the amphipods in each
+/// room from the top down.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagram {
+    pub layout: Layout,
+    pub rooms: Vec<Vec<Amphipod>>,
+}
+
+impl Diagram {
+    /// Inserts rows below the top of every room, each drawn like a row of
+    /// the diagram with one amphipod per room.
+    pub fn unfold(&mut self, rows: &[&str]) -> Result<(), &'static str> {
+        for (depth, row) in rows.iter().enumerate() {
+            let row: Vec<_> = row
+                .chars()
+                .filter_map(|c| Amphipod::try_from(c).ok())
+                .collect();
+            if row.len() != self.rooms.len() {
+                return Err("unfolded row doesn't fit the rooms");
+            }
+            for (room, a) in self.rooms.iter_mut().zip(row) {
+                room.insert(depth + 1, a);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Where an amphipod stands. Rooms go by the kind they are for, and depth 0
+/// is the cell next to the hallway.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    Hall(usize),
+    Room(Amphipod, usize),
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::Hall(i) => write!(f, "hallway {}", i),
+            Location::Room(a, depth) => write!(f, "room {} depth {}", a, depth),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Move {
+    pub amphipod: Amphipod,
+    pub from: Location,
+    pub to: Location,
+    pub energy: usize,
+}
+
+impl Move {
+    pub fn new(amphipod: Amphipod, from: Location, to: Location, energy: usize) -> Self {
+        Self {
+            amphipod,
+            from,
+            to,
+            energy,
+        }
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} from {} to {} for {}",
+            self.amphipod, self.from, self.to, self.energy
+        )
+    }
+}
+
+/// A burrow cell by cell, displayed the way the puzzle draws it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Picture {
+    layout: Layout,
+    hall: Vec<Option<Amphipod>>,
+    // each room from the top down.
+    rooms: Vec<Vec<Option<Amphipod>>>,
+}
+
+impl Picture {
+    pub fn new(diagram: &Diagram) -> Self {
+        Self {
+            layout: diagram.layout.clone(),
+            hall: vec![None; diagram.layout.hallway],
+            rooms: diagram
+                .rooms
+                .iter()
+                .map(|room| room.iter().map(|&a| Some(a)).collect())
+                .collect(),
+        }
+    }
+
+    /// Makes the move if nothing is in the way and returns the energy it
+    /// takes, whatever energy the move itself claims.
+    pub fn apply(&mut self, m: &Move) -> Result<usize, String> {
+        let mut next = self.clone();
+        let from = next.cell_mut(m.from)?;
+        if *from != Some(m.amphipod) {
+            return Err(format!("no {} at {}", m.amphipod, m.from));
+        }
+        *from = None;
+
+        // the hallway between the two ends, then the rooms above them.
+        let (start, end) = (next.column(m.from), next.column(m.to));
+        let mut path: Vec<_> = (start.min(end)..=start.max(end))
+            .map(Location::Hall)
+            .collect();
+        let mut steps = start.abs_diff(end);
+        for l in [m.from, m.to] {
+            if let Location::Room(a, depth) = l {
+                path.extend((0..=depth).map(|d| Location::Room(a, d)));
+                steps += depth + 1;
+            }
+        }
+
+        for l in path {
+            if let Some(a) = *next.cell_mut(l)? {
+                return Err(format!("{} is in the way at {}", a, l));
+            }
+        }
+
+        *next.cell_mut(m.to)? = Some(m.amphipod);
+        *self = next;
+        Ok(steps * self.layout.energy[m.amphipod.0 as usize])
+    }
+
+    fn cell_mut(&mut self, l: Location) -> Result<&mut Option<Amphipod>, String> {
+        let cell = match l {
+            Location::Hall(i) => self.hall.get_mut(i),
+            Location::Room(a, depth) => self
+                .rooms
+                .get_mut(a.0 as usize)
+                .and_then(|room| room.get_mut(depth)),
+        };
+        cell.ok_or_else(|| format!("{} is outside the burrow", l))
+    }
+
+    fn column(&self, l: Location) -> usize {
+        match l {
+            Location::Hall(i) => i,
+            Location::Room(a, _) => self.layout.entrances[a.0 as usize],
+        }
+    }
+}
+
+impl fmt::Display for Picture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let cell = |a: Option<Amphipod>| a.map_or('.', |a| a.to_string().chars().next().unwrap());
+        let entrances = &self.layout.entrances;
+        let first = entrances.iter().min().copied().unwrap_or(0);
+        let last = entrances.iter().max().copied().unwrap_or(0);
+
+        writeln!(f, "{}", "#".repeat(self.hall.len() + 2))?;
+        let hall: String = self.hall.iter().map(|&a| cell(a)).collect();
+        writeln!(f, "#{}#", hall)?;
+
+        // every row is drawn across the whole burrow for the top of the
+        // rooms and indented to just around them below that.
+        let depth = self.rooms.iter().map(|r| r.len()).max().unwrap_or(0);
+        for d in 0..depth {
+            let (indent, width) = match d {
+                0 => (0, self.hall.len() + 2),
+                _ => (first, last + 3),
+            };
+            let row: String = (0..width)
+                .map(|col| match entrances.iter().position(|&e| e + 1 == col) {
+                    _ if col < indent => ' ',
+                    Some(r) => cell(self.rooms[r].get(d).copied().flatten()),
+                    None => '#',
+                })
+                .collect();
+            writeln!(f, "{}", row)?;
+        }
+
+        write!(f, "{}{}", " ".repeat(first), "#".repeat(last + 3 - first))
+    }
+}
+
+/// Every burrow along the way, starting with the diagram itself, and the
+/// energy all the moves take together.
+pub fn replay(diagram: &Diagram, moves: &[Move]) -> Result<(Vec<Picture>, usize), String> {
+    let mut picture = Picture::new(diagram);
+    let mut frames = vec![picture.clone()];
+    let mut energy = 0;
+
+    for (i, m) in moves.iter().enumerate() {
+        energy += picture
+            .apply(m)
+            .map_err(|e| format!("move {} ({}): {}", i + 1, m, e))?;
+        frames.push(picture.clone());
+    }
+
+    Ok((frames, energy))
+}
+
+/// Solves the burrow and plays the moves back one frame at a time.
+pub fn animate<W: Write>(input: &str, unfold: bool, mut out: W, delay: Duration) -> io::Result<()> {
+    let (diagram, cost, moves) =
+        organize(input, unfold).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let (frames, _) = replay(&diagram, &moves).expect("solution should replay");
+
+    let mut spent = 0;
+    for (i, frame) in frames.iter().enumerate() {
+        // clear the screen and draw from the top left.
+        write!(out, "\x1b[2J\x1b[H{}\n\n", frame)?;
+        match i.checked_sub(1).map(|i| moves[i]) {
+            Some(m) => {
+                spent += m.energy;
+                writeln!(out, "{}, {} of {} spent", m, spent, cost)?;
+            }
+            None => writeln!(out, "{} moves, {} energy", moves.len(), cost)?,
+        }
+        out.flush()?;
+        thread::sleep(delay);
+    }
+
+    Ok(())
+}
 
 #[derive(Clone, PartialEq, Eq, Debug)]
 struct Room {
@@ -137,7 +532,7 @@ impl Room {
         }
     }
 
-    // Returns energy to push if push was successful. It is assumed you are
+    // Returns steps to push if push was successful. It is assumed you are
     // placing the only allowed Amphipod.
     fn push(&mut self) -> Option<usize> {
         if self.cells.len() != 0 {
@@ -152,20 +547,20 @@ impl Room {
         let steps = self.size - self.filled;
         self.filled += 1;
 
-        Some(steps * self.wanted.energy())
+        Some(steps)
     }
 
-    // Returns energy to leave room and value removed.
+    // Returns steps to leave room and value removed.
     fn pop(&mut self) -> Option<(usize, Amphipod)> {
         let ret = self.cells.pop()?;
         let steps = self.size - self.spaces_taken();
-        Some((steps * ret.energy(), ret))
+        Some((steps, ret))
     }
 
     fn peak(&self) -> Option<(usize, Amphipod)> {
         let ret = self.cells.last()?;
         let steps = self.size - self.spaces_taken() + 1;
-        Some((steps * ret.energy(), *ret))
+        Some((steps, *ret))
     }
 
     fn is_complete(&self) -> bool {
@@ -177,25 +572,32 @@ impl Room {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Packed(u128),
+    Cells(Vec<u8>),
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Burrow {
-    hall: [Option<Amphipod>; HALLWAY_LENGTH],
-    rooms: [Room; 4],
+    layout: Rc<Layout>,
+    hall: Vec<Option<Amphipod>>,
+    rooms: Vec<Room>,
 }
 
 impl Burrow {
-    fn new(room_occupants: [Vec<Amphipod>; 4]) -> Self {
-        let [a, b, c, d] = room_occupants;
-        let rooms = [
-            Room::new(Amphipod::A, a),
-            Room::new(Amphipod::B, b),
-            Room::new(Amphipod::C, c),
-            Room::new(Amphipod::D, d),
-        ];
+    fn new(diagram: Diagram) -> Self {
+        let rooms = diagram
+            .rooms
+            .into_iter()
+            .enumerate()
+            .map(|(i, cells)| Room::new(Amphipod(i as u8), cells))
+            .collect();
 
         Self {
-            hall: [None; HALLWAY_LENGTH],
+            hall: vec![None; diagram.layout.hallway],
             rooms,
+            layout: Rc::new(diagram.layout),
         }
     }
 
@@ -203,13 +605,68 @@ impl Burrow {
         self.rooms.iter().all(|x| x.is_complete())
     }
 
-    fn get_room_mut(&mut self, a: Amphipod) -> &mut Room {
-        match a {
-            Amphipod::A => &mut self.rooms[0],
-            Amphipod::B => &mut self.rooms[1],
-            Amphipod::C => &mut self.rooms[2],
-            Amphipod::D => &mut self.rooms[3],
+    // A lower bound on the energy left, as if nothing were ever in the way:
+    // everyone walks to their room's entrance, stepping out and back in if
+    // they have to let others out from under them, then the rooms fill up
+    // from the bottom.
+    fn heuristic(&self) -> usize {
+        let mut energy = 0;
+
+        for (i, a) in self.hall.iter().enumerate() {
+            if let Some(a) = *a {
+                energy += i.abs_diff(self.entrance(a)) * self.energy(a);
+            }
         }
+
+        for room in self.rooms.iter() {
+            let here = self.entrance(room.wanted);
+            for (i, &a) in room.cells.iter().enumerate() {
+                let up = room.size - room.filled - i;
+                let across = match a == room.wanted {
+                    true => 2,
+                    false => here.abs_diff(self.entrance(a)),
+                };
+                energy += (up + across) * self.energy(a);
+            }
+
+            let entering = room.size - room.filled;
+            energy += entering * (entering + 1) / 2 * self.energy(room.wanted);
+        }
+
+        energy
+    }
+
+    // Every cell as 0 when empty and kind + 1 otherwise, packed into a u128
+    // when they fit, which they do for the puzzle.
+    fn key(&self) -> Key {
+        let hall = self.hall.iter().copied();
+        let rooms = self.rooms.iter().flat_map(|room| {
+            (0..room.size).map(move |i| match i.checked_sub(room.filled) {
+                None => Some(room.wanted),
+                Some(i) => room.cells.get(i).copied(),
+            })
+        });
+        let cells = hall.chain(rooms).map(|a| a.map_or(0, |a| a.0 + 1));
+
+        let bits = u8::BITS - (self.rooms.len() as u8).leading_zeros();
+        let len = self.hall.len() + self.rooms.iter().map(|r| r.size).sum::<usize>();
+        if len * bits as usize <= 128 {
+            Key::Packed(cells.fold(0, |acc, c| acc << bits | c as u128))
+        } else {
+            Key::Cells(cells.collect())
+        }
+    }
+
+    fn get_room_mut(&mut self, a: Amphipod) -> &mut Room {
+        &mut self.rooms[a.0 as usize]
+    }
+
+    fn energy(&self, a: Amphipod) -> usize {
+        self.layout.energy[a.0 as usize]
+    }
+
+    fn entrance(&self, a: Amphipod) -> usize {
+        self.layout.entrances[a.0 as usize]
     }
 
     // Returns the steps of moving a value from one location to another in the
@@ -228,26 +685,22 @@ impl Burrow {
         }
     }
 
-    // Returns the energy used to move if successful.
-    fn move_to_room(&mut self, a: Amphipod, from_hall: usize) -> Option<usize> {
-        let hall_steps = self.hall_move_steps(from_hall, a.room_entrance())?;
-        let room_cost = self.get_room_mut(a).push()?;
-        Some(hall_steps * a.energy() + room_cost)
+    // Returns the energy used to move and where the amphipod ended up if
+    // successful.
+    fn move_to_room(&mut self, a: Amphipod, from_hall: usize) -> Option<(usize, Location)> {
+        let hall_steps = self.hall_move_steps(from_hall, self.entrance(a))?;
+        let room_steps = self.get_room_mut(a).push()?;
+        let energy = (hall_steps + room_steps) * self.energy(a);
+        Some((energy, Location::Room(a, room_steps - 1)))
     }
 
     fn iter_rooms(&self) -> impl Iterator<Item = (Amphipod, &Room)> {
-        [
-            (Amphipod::A, &self.rooms[0]),
-            (Amphipod::B, &self.rooms[1]),
-            (Amphipod::C, &self.rooms[2]),
-            (Amphipod::D, &self.rooms[3]),
-        ]
-        .into_iter()
+        self.rooms.iter().map(|room| (room.wanted, room))
     }
 
     // Returns the largest unoccupied range containing mid in the form of [i, j].
     // mid may be occupied.
-    fn open_hall_range(&self, mid: usize) -> impl Iterator<Item = usize> {
+    fn open_hall_range(&self, mid: usize) -> impl Iterator<Item = usize> + '_ {
         let start = (&self.hall[..mid])
             .iter()
             .enumerate()
@@ -265,35 +718,17 @@ impl Burrow {
             .map(|(i, _)| i + mid + 1)
             .unwrap_or(self.hall.len());
 
-        (start..end).filter(|x| ![2, 4, 6, 8].contains(x))
+        (start..end).filter(|x| !self.layout.entrances.contains(x))
     }
 }
 
+/// A kind of amphipod, lettered from A.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Amphipod {
-    A,
-    B,
-    C,
-    D,
-}
+pub struct Amphipod(u8);
 
-impl Amphipod {
-    fn energy(&self) -> usize {
-        match &self {
-            Amphipod::A => 1,
-            Amphipod::B => 10,
-            Amphipod::C => 100,
-            Amphipod::D => 1000,
-        }
-    }
-
-    fn room_entrance(&self) -> usize {
-        match &self {
-            Amphipod::A => 2,
-            Amphipod::B => 4,
-            Amphipod::C => 6,
-            Amphipod::D => 8,
-        }
+impl fmt::Display for Amphipod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", (b'A' + self.0) as char)
     }
 }
 
@@ -301,10 +736,7 @@ impl TryFrom<char> for Amphipod {
     type Error = ();
     fn try_from(value: char) -> Result<Self, Self::Error> {
         match value {
-            'A' => Ok(Amphipod::A),
-            'B' => Ok(Amphipod::B),
-            'C' => Ok(Amphipod::C),
-            'D' => Ok(Amphipod::D),
+            'A'..='Z' => Ok(Amphipod(value as u8 - b'A')),
             _ => Err(()),
         }
     }
@@ -314,22 +746,81 @@ mod parser {
     use super::*;
     use crate::lib::combinators::*;
 
-    pub fn parse(input: &str) -> IResult<&str, [Vec<Amphipod>; 4], &'static str> {
-        let amphipods: Vec<_> = input
-            .chars()
-            .filter_map(|c| Amphipod::try_from(c).ok())
-            .collect();
+    // The hallway line, as how far in the hallway starts and its length.
+    fn hallway(input: &str) -> IResult<&str, (usize, usize)> {
+        let wall = recognize(pair(space0, tag("#")));
+        let hallway = terminated(pair(wall, is_a(".")), tag("#"));
+        map(hallway, |(wall, cells): (&str, &str)| {
+            (wall.len(), cells.len())
+        })(input)
+    }
+
+    // A row of rooms, as each amphipod along with its column in the line.
+    fn room_row(input: &str) -> IResult<&str, Vec<(usize, Amphipod)>> {
+        let column = map(rest_len, |rest: usize| input.len() - rest);
+        let amphipod = map_res(anychar, Amphipod::try_from);
+        let cell = terminated(pair(column, amphipod), is_a("#"));
+        preceded(pair(space0, is_a("#")), many1(cell))(input)
+    }
+
+    fn energy(input: &str) -> IResult<&str, Vec<usize>> {
+        let costs = separated_list1(space1, uint);
+        preceded(pair(tag("energy:"), space1), costs)(input)
+    }
+
+    // Each room opens onto the hallway cell above its top amphipod. An
+    // optional line like `energy: 1 10 100 1000` after the diagram sets the
+    // cost of each kind.
+    pub fn parse(input: &str) -> IResult<&str, Diagram> {
+        let top = pair(is_a("#"), line_ending);
+        let rows = separated_list1(line_ending, room_row);
+        let bottom = tuple((line_ending, space0, is_a("#")));
+        let parser = tuple((
+            preceded(top, hallway),
+            preceded(line_ending, rows),
+            preceded(bottom, opt(preceded(line_ending, energy))),
+        ));
+        let diagram = map_res(parser, |((indent, hallway), rows, energy)| {
+            diagram(indent, hallway, rows, energy)
+        });
+        complete(diagram)(input)
+    }
 
-        if amphipods.len() % 4 != 0 {
-            return IResult::Err(nom::Err::Error("unexpected number of amphipods"));
+    // Checks that every row has its amphipods in the same columns, under the
+    // hallway, and that there's a room for every kind.
+    fn diagram(
+        indent: usize,
+        hallway: usize,
+        rows: Vec<Vec<(usize, Amphipod)>>,
+        energy: Option<Vec<usize>>,
+    ) -> Result<Diagram, &'static str> {
+        let columns = |row: &[(usize, Amphipod)]| row.iter().map(|&(c, _)| c).collect::<Vec<_>>();
+        let top = columns(&rows[0]);
+        if rows.iter().any(|row| columns(row) != top) {
+            return Err("rooms don't line up");
         }
 
-        let mut ret = [vec![], vec![], vec![], vec![]];
-        for (i, a) in amphipods.into_iter().enumerate() {
-            ret[i % 4].push(a);
+        let entrances = top
+            .iter()
+            .map(|c| c.checked_sub(indent).filter(|&e| e < hallway))
+            .collect::<Option<Vec<_>>>()
+            .ok_or("room outside the hallway")?;
+
+        let mut rooms = vec![vec![]; entrances.len()];
+        for row in rows {
+            for (room, (_, a)) in rooms.iter_mut().zip(row) {
+                room.push(a);
+            }
+        }
+        if rooms.iter().flatten().any(|a| a.0 as usize >= rooms.len()) {
+            return Err("amphipod without a room");
         }
 
-        IResult::Ok((&input[input.len()..], ret))
+        let layout = match energy {
+            Some(energy) => Layout::with_energy(hallway, entrances, energy)?,
+            None => Layout::new(hallway, entrances)?,
+        };
+        Ok(Diagram { layout, rooms })
     }
 }
 
@@ -340,8 +831,8 @@ mod tests {
     const EXAMPLE_INPUT: &str = "#############
 #...........#
 ###B#C#B#D###
-    #A#D#C#A#
-    #########";
+  #A#D#C#A#
+  #########";
 
     #[test]
     fn problem1_test() {
@@ -355,14 +846,17 @@ mod tests {
 
     #[test]
     fn test_open_hall_range() {
-        let mut b = Burrow::new([vec![], vec![], vec![], vec![]]);
+        let mut b = Burrow::new(Diagram {
+            layout: Layout::new(11, vec![2, 4, 6, 8]).unwrap(),
+            rooms: vec![vec![]; 4],
+        });
         assert_eq!(
             b.open_hall_range(2).collect::<Vec<_>>(),
             vec![0, 1, 3, 5, 7, 9, 10],
         );
 
-        b.hall[4] = Some(Amphipod::A);
-        b.hall[8] = Some(Amphipod::A);
+        b.hall[4] = Some(Amphipod(0));
+        b.hall[8] = Some(Amphipod(0));
 
         assert_eq!(b.open_hall_range(1).collect::<Vec<_>>(), vec![0, 1, 3]);
         assert_eq!(
@@ -372,4 +866,179 @@ mod tests {
         assert_eq!(b.open_hall_range(5).collect::<Vec<_>>(), vec![5, 7]);
         assert_eq!(b.open_hall_range(10).collect::<Vec<_>>(), vec![9, 10]);
     }
+
+    #[test]
+    fn layout_test() {
+        let diagram = parser::parse("#######\n#.....#\n##B#A##\n #####")
+            .unwrap()
+            .1;
+        assert_eq!(diagram.layout, Layout::new(5, vec![1, 3]).unwrap());
+        assert_eq!(diagram.rooms, vec![vec![Amphipod(1)], vec![Amphipod(0)]]);
+
+        // A steps aside so B can go straight across.
+        assert_eq!(
+            search(Burrow::new(diagram.clone()), Options::default())
+                .unwrap()
+                .cost,
+            2 + 40 + 4
+        );
+
+        let cheap = parser::parse("#######\n#.....#\n##B#A##\n #####\nenergy: 1 1")
+            .unwrap()
+            .1;
+        assert_eq!(cheap.layout.energy, vec![1, 1]);
+        assert_eq!(
+            search(Burrow::new(cheap), Options::default()).unwrap().cost,
+            10
+        );
+        assert!(parser::parse("#######\n#.....#\n##B#A##\nenergy: 1").is_err());
+        assert!(parser::parse("#######\n#.....#\n##B#A##\nenergy: 1 x").is_err());
+
+        // part two's rows only fit four rooms.
+        let two_rooms = "#######\n#.....#\n##B#A##\n #####";
+        assert_eq!(organize(two_rooms, false).unwrap().1, 46);
+        assert_eq!(
+            organize(two_rooms, true).unwrap_err(),
+            "unfolded row doesn't fit the rooms"
+        );
+
+        // too many of a kind for its room, and none for the other.
+        for rooms in ["##A#A##", "##B#B##"] {
+            let burrow = format!("#######\n#.....#\n{}\n  #####", rooms);
+            assert_eq!(
+                organize(&burrow, false).unwrap_err(),
+                "every kind needs as many amphipods as its room holds"
+            );
+        }
+
+        let mut deep = diagram;
+        deep.unfold(&["#A#B#", "#B#A#"]).unwrap();
+        assert_eq!(
+            deep.rooms,
+            vec![
+                vec![Amphipod(1), Amphipod(0), Amphipod(1)],
+                vec![Amphipod(0), Amphipod(1), Amphipod(0)],
+            ]
+        );
+        assert!(deep.unfold(&["#A#B#C#"]).is_err());
+
+        // five kinds over a longer hallway, entrances not evenly spaced.
+        let input = "##############
+#............#
+###B#A#C#E#D##
+  #A#B#C#D#E#
+  ###########";
+        let diagram = parser::parse(input).unwrap().1;
+        assert_eq!(diagram.layout.entrances, vec![2, 4, 6, 8, 10]);
+        assert_eq!(diagram.layout.energy, vec![1, 10, 100, 1000, 10000]);
+        assert_eq!(
+            search(Burrow::new(diagram), Options::default())
+                .unwrap()
+                .cost,
+            46 + 46000
+        );
+
+        assert!(parser::parse("#####\n#.A.#\n##B##").is_err());
+        assert!(parser::parse("#######\n#.....#\n##B#C##\n #####").is_err());
+        assert!(parser::parse("#######\n#.....#\n##B#A##\n #A#B#A#").is_err());
+        // as many amphipods in each row, but not under each other.
+        assert!(parser::parse("#######\n#.....#\n##B#A##\n  #A#B#\n  #####").is_err());
+        assert!(parser::parse("#####\n#...#\n###B#A#\n  #####").is_err());
+
+        // 10^20 doesn't fit in a usize, so 21 kinds need their own costs.
+        let letters: String = ('A'..='U').map(|c| format!("{}#", c)).collect();
+        let hall = ".".repeat(43);
+        let wide = format!(
+            "#{0}#\n#{1}#\n###{2}##\n  {0}",
+            "#".repeat(43),
+            hall,
+            letters
+        );
+        assert!(parser::parse(&wide).is_err());
+        let costs = vec!["1"; 21].join(" ");
+        let wide = format!("{}\nenergy: {}", wide, costs);
+        assert_eq!(parser::parse(&wide).unwrap().1.layout.energy, vec![1; 21]);
+    }
+
+    #[test]
+    fn replay_test() {
+        let (diagram, cost, moves) = organize(EXAMPLE_INPUT, false).unwrap();
+        assert_eq!(cost, 12521);
+        assert_eq!(moves.iter().map(|m| m.energy).sum::<usize>(), cost);
+
+        let (frames, energy) = replay(&diagram, &moves).unwrap();
+        assert_eq!(energy, cost);
+        assert_eq!(frames.len(), moves.len() + 1);
+        assert_eq!(
+            frames[0].to_string(),
+            "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########"
+        );
+        assert_eq!(
+            frames.last().unwrap().to_string(),
+            "#############
+#...........#
+###A#B#C#D###
+  #A#B#C#D#
+  #########"
+        );
+
+        // the puzzle's first move, then one that has to go through it.
+        let b = Amphipod(1);
+        let mut picture = Picture::new(&diagram);
+        let first = Move::new(b, Location::Room(Amphipod(2), 0), Location::Hall(3), 40);
+        assert_eq!(picture.apply(&first), Ok(40));
+        assert!(picture
+            .to_string()
+            .contains("\n#...B.......#\n###B#C#.#D###\n"));
+
+        let blocked = Move::new(b, Location::Room(Amphipod(0), 0), Location::Hall(10), 0);
+        assert_eq!(
+            picture.apply(&blocked),
+            Err("B is in the way at hallway 3".to_string())
+        );
+        let missing = Move::new(b, Location::Hall(4), Location::Hall(5), 0);
+        assert_eq!(
+            picture.apply(&missing),
+            Err("no B at hallway 4".to_string())
+        );
+        assert!(replay(&diagram, &[first, first])
+            .unwrap_err()
+            .starts_with("move 2"));
+    }
+
+    #[test]
+    fn search_test() {
+        let diagram = parser::parse(EXAMPLE_INPUT).unwrap().1;
+        let burrow = Burrow::new(diagram.clone());
+        assert!(burrow.heuristic() <= 12521);
+
+        let before = Options {
+            heuristic: false,
+            dedup: false,
+        };
+        let old = search(burrow.clone(), before).unwrap();
+        let new = search(burrow.clone(), Options::default()).unwrap();
+        assert_eq!((old.cost, new.cost), (12521, 12521));
+        assert!(new.stats.expanded * 10 < old.stats.expanded);
+        assert!(compare_searches(EXAMPLE_INPUT, false)
+            .unwrap()
+            .ends_with(&format!(
+                "yes        yes    {:>8}  {:>8}   12521\n",
+                new.stats.expanded, new.stats.pushed
+            )));
+
+        // 11 hallway cells and 8 room cells at 3 bits each fit in a u128.
+        assert!(matches!(burrow.key(), Key::Packed(_)));
+        let mut moved = burrow.clone();
+        moved.hall[0] = moved.rooms[0].pop().map(|(_, a)| a);
+        assert_ne!(burrow.key(), moved.key());
+
+        let mut wide = diagram;
+        wide.layout.hallway = 40;
+        assert!(matches!(Burrow::new(wide).key(), Key::Cells(_)));
+    }
 }
@@ -1,62 +1,87 @@
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::fmt;
 
 pub fn problem1(input: &str) -> String {
-    let program = parser::parse(input).unwrap().1;
-    dfs_model_number(&program, true)
+    let program = optimizer::optimize(&parser::parse(input).unwrap().1);
+    symbolic_model_number(&program, true).unwrap_or_else(|| dfs_model_number(&program, true))
 }
 
 pub fn problem2(input: &str) -> String {
-    let program = parser::parse(input).unwrap().1;
-    dfs_model_number(&program, false)
+    let program = optimizer::optimize(&parser::parse(input).unwrap().1);
+    symbolic_model_number(&program, false).unwrap_or_else(|| dfs_model_number(&program, false))
+}
+
+// Reads the model number off the program's digit constraints. The answer is
+// checked on the real machine in case an assumed eql was the wrong call.
+fn symbolic_model_number(program: &[Instruction], max: bool) -> Option<String> {
+    let digits = symbolic::model_number(program, max)?;
+    is_valid_model_number(program, &digits).then(|| to_model_number(&digits))
+}
+
+fn is_valid_model_number(program: &[Instruction], digits: &[i64]) -> bool {
+    let mut state = RegisterState::default();
+    for (k, &d) in digits.iter().enumerate() {
+        let last = k + 1 == digits.len();
+        match run_machine(program, state, d) {
+            InterruptState::InputRequred(s) if !last => state = s,
+            InterruptState::Complete(s) if last => return s.z == 0,
+            _ => return false,
+        }
+    }
+
+    false
+}
+
+fn to_model_number(digits: &[i64]) -> String {
+    String::from_iter(
+        digits
+            .iter()
+            .map(|x| char::from_digit(*x as u32, 10).unwrap()),
+    )
 }
 
 fn dfs_model_number(program: &[Instruction], max: bool) -> String {
     let mut path = Vec::new();
     dfs_model_number_rec(
         max,
-        program,
+        &mut compiled::Compiled::new(program),
         &mut path,
-        &mut HashSet::new(),
-        RegisterState::default(),
+        0,
+        [0; 4],
     );
 
-    String::from_iter(
-        path.iter()
-            .map(|x| char::from_digit(*x as u32, 10).unwrap()),
-    )
+    to_model_number(&path)
 }
 
 fn dfs_model_number_rec(
     max: bool,
-    program: &[Instruction],
+    program: &mut compiled::Compiled,
     path: &mut Vec<i64>,
-    seen: &mut HashSet<RegisterState>,
-    state: RegisterState,
+    block: usize,
+    regs: compiled::Registers,
 ) -> bool {
-    if seen.contains(&state) {
+    if block == program.len() {
+        return regs[Register::Z as usize] == 0;
+    }
+
+    let regs = program.live_in(block, regs);
+    if !program.can_finish(block, regs) {
         return false;
     }
 
-    let nums: Box<dyn Iterator<Item = i64>> = match max {
-        true => Box::new((1..10).rev()),
-        false => Box::new(1..10),
-    };
+    // the program is only searched once, so a state whose transitions are
+    // already cached has been tried and found to lead nowhere.
+    let (transitions, first_visit) = program.transitions(block, regs);
+    if !first_visit {
+        return false;
+    }
 
-    for i in nums {
+    for i in 1..10 {
+        let i = if max { 10 - i } else { i };
         path.push(i);
-        match run_machine(program, state, i) {
-            InterruptState::InputRequred(s) => {
-                let ok = dfs_model_number_rec(max, program, path, seen, s);
-                seen.insert(s);
-                if ok {
-                    return true;
-                }
-            }
-            InterruptState::Crashed => (),
-            InterruptState::Complete(s) => {
-                if s.z == 0 {
-                    return true;
-                }
+        if let Some(next) = program.next(transitions, i) {
+            if dfs_model_number_rec(max, program, path, block + 1, next) {
+                return true;
             }
         }
         path.pop();
@@ -65,6 +90,154 @@ fn dfs_model_number_rec(
     false
 }
 
+/// Every model number a program accepts. Works like `dfs_model_number`, but
+/// the memo keeps how many digit strings take each state to z == 0 rather
+/// than only whether any do, which is enough to count, index and stream the
+/// valid numbers without searching anything twice.
+pub struct ModelNumbers {
+    program: compiled::Compiled,
+    // valid completions from the start of each block.
+    seen: HashMap<(usize, compiled::Registers), u64>,
+}
+
+impl ModelNumbers {
+    pub fn new(program: &[Instruction]) -> Self {
+        ModelNumbers {
+            program: compiled::Compiled::new(program),
+            seen: HashMap::new(),
+        }
+    }
+
+    pub fn count(&mut self) -> u64 {
+        self.completions(0, [0; 4])
+    }
+
+    /// The k-th largest valid model number, counting from 0.
+    pub fn nth_largest(&mut self, k: u64) -> Option<String> {
+        if k >= self.count() {
+            return None;
+        }
+
+        let mut k = k;
+        let mut regs = [0; 4];
+        let mut path = Vec::new();
+        'digit: while path.len() < self.program.len() {
+            let block = path.len();
+            for i in (1..10).rev() {
+                // consecutive k share most of their walk, hence the cache.
+                let Some(next) = self.program.cached_step(block, regs, i) else {
+                    continue;
+                };
+                let n = self.completions(block + 1, next);
+                if k < n {
+                    path.push(i);
+                    regs = next;
+                    continue 'digit;
+                }
+                k -= n;
+            }
+            unreachable!("fewer model numbers than counted");
+        }
+
+        Some(to_model_number(&path))
+    }
+
+    /// The k-th smallest valid model number, counting from 0.
+    pub fn nth_smallest(&mut self, k: u64) -> Option<String> {
+        let count = self.count();
+        (k < count).then(|| self.nth_largest(count - 1 - k).unwrap())
+    }
+
+    /// Streams the valid model numbers, largest first.
+    pub fn iter(&mut self) -> Box<dyn Iterator<Item = String> + '_> {
+        let count = self.count();
+        Box::new((0..count).map(move |k| self.nth_largest(k).unwrap()))
+    }
+
+    fn completions(&mut self, block: usize, regs: compiled::Registers) -> u64 {
+        if block == self.program.len() {
+            return (regs[Register::Z as usize] == 0) as u64;
+        }
+
+        let regs = self.program.live_in(block, regs);
+        if !self.program.can_finish(block, regs) {
+            return 0;
+        }
+        if let Some(&n) = self.seen.get(&(block, regs)) {
+            return n;
+        }
+
+        let mut n = 0;
+        for i in 1..10 {
+            if let Some(next) = self.program.step(block, regs, i) {
+                n += self.completions(block + 1, next);
+            }
+        }
+
+        self.seen.insert((block, regs), n);
+        n
+    }
+}
+
+/// Checks a model number on the machine. When it's rejected, the error names
+/// the first digit constraint it breaks, or failing that how the program
+/// ended.
+pub fn check_model_number(program: &[Instruction], number: &str) -> Result<(), String> {
+    let mut digits = Vec::new();
+    for (i, c) in number.chars().enumerate() {
+        match c.to_digit(10) {
+            Some(0) => return Err(format!("d{} is 0, digits run from 1 through 9", i)),
+            Some(d) => digits.push(d as i64),
+            None => return Err(format!("'{}' is not a digit", c)),
+        }
+    }
+
+    let inputs = program
+        .iter()
+        .filter(|inst| matches!(inst, Instruction::Inp(_)))
+        .count();
+    if digits.len() != inputs {
+        return Err(format!("expected {} digits, got {}", inputs, digits.len()));
+    }
+
+    if is_valid_model_number(program, &digits) {
+        return Ok(());
+    }
+
+    // the constraints assume every earlier one held, so only the first
+    // broken one says anything.
+    if let Some(ex) = symbolic::run(program) {
+        for c in ex.constraints.iter() {
+            let (lhs, rhs) = (c.lhs.eval(&digits), c.rhs.eval(&digits));
+            if lhs == rhs {
+                continue;
+            }
+
+            return Err(match (c.lhs.as_offset_input(), c.rhs.as_offset_input()) {
+                (Some((i, a)), Some((j, b))) => format!(
+                    "d{} must be d{}{} = {}, but it is {}",
+                    j,
+                    i,
+                    optimizer::signed(a - b),
+                    digits[i] + a - b,
+                    digits[j]
+                ),
+                _ => format!("{} does not hold", c),
+            });
+        }
+    }
+
+    let mut state = RegisterState::default();
+    for &d in digits.iter() {
+        match run_machine(program, state, d) {
+            InterruptState::InputRequred(s) => state = s,
+            InterruptState::Crashed => return Err("the program crashes".to_string()),
+            InterruptState::Complete(s) => return Err(format!("z ends at {}", s.z)),
+        }
+    }
+    Err("the program wants more input".to_string())
+}
+
 fn run_machine(program: &[Instruction], state: RegisterState, input: i64) -> InterruptState {
     let mut m = Machine {
         program: program,
@@ -86,7 +259,7 @@ enum InterruptState {
     Complete(RegisterState),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     Inp(Register),
     Add(Register, Operand),
@@ -96,7 +269,7 @@ pub enum Instruction {
     Eql(Register, Operand),
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Operand {
     Register(Register),
     Value(i64),
@@ -111,7 +284,7 @@ impl Operand {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Register {
     W,
     X,
@@ -119,6 +292,41 @@ pub enum Register {
     Z,
 }
 
+// Displays instructions the way they are written in ALU source.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Inp(r) => write!(f, "inp {}", r),
+            Instruction::Add(r, o) => write!(f, "add {} {}", r, o),
+            Instruction::Mul(r, o) => write!(f, "mul {} {}", r, o),
+            Instruction::Div(r, o) => write!(f, "div {} {}", r, o),
+            Instruction::Mod(r, o) => write!(f, "mod {} {}", r, o),
+            Instruction::Eql(r, o) => write!(f, "eql {} {}", r, o),
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(r) => write!(f, "{}", r),
+            Operand::Value(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Register::W => "w",
+            Register::X => "x",
+            Register::Y => "y",
+            Register::Z => "z",
+        };
+        write!(f, "{}", name)
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Machine<'a> {
     program: &'a [Instruction],
@@ -220,6 +428,1254 @@ impl RegisterState {
     }
 }
 
+// Runs a program over expressions of the input digits instead of numbers.
+// Every eql that the digit ranges can't settle is assumed to hold and kept as
+// a constraint, which for MONAD are exactly the pop blocks that have to match
+// to bring z back down to 0.
+mod symbolic {
+    use super::{Instruction, Operand, Register};
+    use std::fmt;
+    use std::rc::Rc;
+
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Expr {
+        Const(i64),
+        // the nth input digit, always 1 through 9.
+        Input(usize),
+        Add(Rc<Expr>, Rc<Expr>),
+        Mul(Rc<Expr>, Rc<Expr>),
+        Div(Rc<Expr>, Rc<Expr>),
+        Mod(Rc<Expr>, Rc<Expr>),
+    }
+
+    use Expr::*;
+
+    const FULL_RANGE: (i64, i64) = (i64::MIN, i64::MAX);
+
+    impl Expr {
+        /// The smallest and largest values the expression can take.
+        pub fn range(&self) -> (i64, i64) {
+            match self {
+                Const(c) => (*c, *c),
+                Input(_) => (1, 9),
+                Add(a, b) => {
+                    let (a, b) = (a.range(), b.range());
+                    (a.0.saturating_add(b.0), a.1.saturating_add(b.1))
+                }
+                Mul(a, b) => {
+                    let (a, b) = (a.range(), b.range());
+                    let products = [
+                        a.0.saturating_mul(b.0),
+                        a.0.saturating_mul(b.1),
+                        a.1.saturating_mul(b.0),
+                        a.1.saturating_mul(b.1),
+                    ];
+                    let min = *products.iter().min().unwrap();
+                    let max = *products.iter().max().unwrap();
+                    (min, max)
+                }
+                Div(a, b) => match **b {
+                    Const(c) if c > 0 => (a.range().0 / c, a.range().1 / c),
+                    Const(c) if c < 0 => (a.range().1 / c, a.range().0 / c),
+                    _ => FULL_RANGE,
+                },
+                // mod crashes on negative numbers, so a is never below 0.
+                Mod(a, b) => match **b {
+                    Const(c) if c > 0 => (0, a.range().1.min(c - 1)),
+                    _ => (0, b.range().1.saturating_sub(1).max(0)),
+                },
+            }
+        }
+
+        fn as_const(&self) -> Option<i64> {
+            match self {
+                Const(c) => Some(*c),
+                _ => None,
+            }
+        }
+
+        /// Matches `Input(i) + c`, including a bare input with c of 0.
+        pub fn as_offset_input(&self) -> Option<(usize, i64)> {
+            match self {
+                Input(i) => Some((*i, 0)),
+                Add(a, b) => match (&**a, &**b) {
+                    (Input(i), Const(c)) => Some((*i, *c)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+
+        /// The value for the given input digits, or None where the machine
+        /// would crash.
+        pub fn eval(&self, inputs: &[i64]) -> Option<i64> {
+            match self {
+                Const(c) => Some(*c),
+                Input(i) => inputs.get(*i).copied(),
+                Add(a, b) => a.eval(inputs)?.checked_add(b.eval(inputs)?),
+                Mul(a, b) => a.eval(inputs)?.checked_mul(b.eval(inputs)?),
+                Div(a, b) => a.eval(inputs)?.checked_div(b.eval(inputs)?),
+                Mod(a, b) => {
+                    let (a, b) = (a.eval(inputs)?, b.eval(inputs)?);
+                    (a >= 0 && b > 0).then(|| a % b)
+                }
+            }
+        }
+    }
+
+    impl fmt::Display for Expr {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match self {
+                Const(c) => write!(f, "{}", c),
+                Input(i) => write!(f, "d{}", i),
+                Add(a, b) => write!(f, "({} + {})", a, b),
+                Mul(a, b) => write!(f, "({} * {})", a, b),
+                Div(a, b) => write!(f, "({} / {})", a, b),
+                Mod(a, b) => write!(f, "({} % {})", a, b),
+            }
+        }
+    }
+
+    fn constant(c: i64) -> Rc<Expr> {
+        Rc::new(Const(c))
+    }
+
+    // Sums and products that overflow are left unfolded.
+    pub fn add(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+        match (a.as_const(), b.as_const()) {
+            (Some(x), Some(y)) if x.checked_add(y).is_some() => return constant(x + y),
+            (Some(0), _) => return b,
+            (_, Some(0)) => return a,
+            (Some(_), None) => return add(b, a),
+            _ => (),
+        }
+
+        // keep constants gathered on the right: (x + c1) + c2 => x + (c1 + c2)
+        if let (Add(x, c1), Some(c2)) = (&*a, b.as_const()) {
+            if let Some(c) = c1.as_const().and_then(|c1| c1.checked_add(c2)) {
+                return add(x.clone(), constant(c));
+            }
+        }
+
+        Rc::new(Add(a, b))
+    }
+
+    pub fn mul(a: Rc<Expr>, b: Rc<Expr>) -> Rc<Expr> {
+        match (a.as_const(), b.as_const()) {
+            (Some(x), Some(y)) if x.checked_mul(y).is_some() => constant(x * y),
+            (Some(0), _) | (_, Some(0)) => constant(0),
+            (Some(1), _) => b,
+            (_, Some(1)) => a,
+            (Some(_), None) => mul(b, a),
+            _ => Rc::new(Mul(a, b)),
+        }
+    }
+
+    // Splits a into (x, c) where a == x * n + c and c is always in [0, n).
+    fn split_multiple(a: &Expr, n: i64) -> Option<(Rc<Expr>, Rc<Expr>)> {
+        let is_multiple = |e: &Expr| match e {
+            Mul(x, m) if m.as_const() == Some(n) && x.range().0 >= 0 => Some(x.clone()),
+            _ => None,
+        };
+
+        if let Some(x) = is_multiple(a) {
+            return Some((x, constant(0)));
+        }
+
+        match a {
+            Add(m, c) => {
+                let x = is_multiple(m)?;
+                let (low, high) = c.range();
+                (low >= 0 && high < n).then(|| (x, c.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns None if b can only be 0.
+    pub fn div(a: Rc<Expr>, b: Rc<Expr>) -> Option<Rc<Expr>> {
+        let n = match b.as_const() {
+            Some(0) => return None,
+            Some(n) => n,
+            None => return Some(Rc::new(Div(a, b))),
+        };
+
+        if let Some(x) = a.as_const() {
+            return Some(constant(x / n));
+        }
+        if n == 1 {
+            return Some(a);
+        }
+
+        let (low, high) = a.range();
+        if n > 0 && low > -n && high < n {
+            return Some(constant(0));
+        }
+        if let Some((x, _)) = split_multiple(&a, n) {
+            return Some(x);
+        }
+
+        Some(Rc::new(Div(a, b)))
+    }
+
+    /// Returns None if the operation is bound to crash.
+    pub fn modulo(a: Rc<Expr>, b: Rc<Expr>) -> Option<Rc<Expr>> {
+        let (low, high) = a.range();
+        if high < 0 || b.range().1 <= 0 {
+            return None;
+        }
+
+        let n = match b.as_const() {
+            Some(n) => n,
+            None => return Some(Rc::new(Mod(a, b))),
+        };
+
+        if let Some(x) = a.as_const() {
+            return Some(constant(x % n));
+        }
+        if low >= 0 && high < n {
+            return Some(a);
+        }
+        if let Some((_, c)) = split_multiple(&a, n) {
+            return Some(c);
+        }
+
+        Some(Rc::new(Mod(a, b)))
+    }
+
+    /// Returns None if the ranges of a and b can't settle the comparison.
+    pub fn eql(a: &Rc<Expr>, b: &Rc<Expr>) -> Option<Rc<Expr>> {
+        if a == b {
+            return Some(constant(1));
+        }
+        if let (Some(x), Some(y)) = (a.as_const(), b.as_const()) {
+            return Some(constant((x == y) as i64));
+        }
+
+        let (a_low, a_high) = a.range();
+        let (b_low, b_high) = b.range();
+        if a_high < b_low || b_high < a_low {
+            return Some(constant(0));
+        }
+
+        None
+    }
+
+    /// lhs == rhs must hold for the program to behave as it was run.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub struct Constraint {
+        pub lhs: Rc<Expr>,
+        pub rhs: Rc<Expr>,
+    }
+
+    impl fmt::Display for Constraint {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{} == {}", self.lhs, self.rhs)
+        }
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct Execution {
+        registers: [Rc<Expr>; 4],
+        pub inputs: usize,
+        pub constraints: Vec<Constraint>,
+    }
+
+    impl Execution {
+        pub fn register(&self, r: Register) -> &Rc<Expr> {
+            &self.registers[r as usize]
+        }
+
+        fn operand(&self, o: &Operand) -> Rc<Expr> {
+            match o {
+                Operand::Register(r) => self.register(*r).clone(),
+                Operand::Value(v) => constant(*v),
+            }
+        }
+    }
+
+    /// Runs the program symbolically. Returns None if it always crashes.
+    pub fn run(program: &[Instruction]) -> Option<Execution> {
+        let mut ex = Execution {
+            registers: [constant(0), constant(0), constant(0), constant(0)],
+            inputs: 0,
+            constraints: Vec::new(),
+        };
+
+        for inst in program {
+            let (r, v) = match inst {
+                Instruction::Inp(r) => {
+                    ex.inputs += 1;
+                    (r, Rc::new(Input(ex.inputs - 1)))
+                }
+                Instruction::Add(r, o) => (r, add(ex.register(*r).clone(), ex.operand(o))),
+                Instruction::Mul(r, o) => (r, mul(ex.register(*r).clone(), ex.operand(o))),
+                Instruction::Div(r, o) => (r, div(ex.register(*r).clone(), ex.operand(o))?),
+                Instruction::Mod(r, o) => (r, modulo(ex.register(*r).clone(), ex.operand(o))?),
+                Instruction::Eql(r, o) => {
+                    let (a, b) = (ex.register(*r).clone(), ex.operand(o));
+                    let v = eql(&a, &b).unwrap_or_else(|| {
+                        ex.constraints.push(Constraint { lhs: a, rhs: b });
+                        constant(1)
+                    });
+                    (r, v)
+                }
+            };
+            ex.registers[*r as usize] = v;
+        }
+
+        Some(ex)
+    }
+
+    /// The largest or smallest input that satisfies every constraint and
+    /// leaves z at 0. Only handles constraints tying one digit to another by a
+    /// fixed offset, which is all MONAD needs.
+    pub fn model_number(program: &[Instruction], max: bool) -> Option<Vec<i64>> {
+        let ex = run(program)?;
+        if ex.register(Register::Z).as_const() != Some(0) {
+            return None;
+        }
+
+        let mut digits = vec![None; ex.inputs];
+        for c in ex.constraints.iter() {
+            // d[i] + a == d[j] + b, so d[j] == d[i] + delta
+            let (i, a) = c.lhs.as_offset_input()?;
+            let (j, b) = c.rhs.as_offset_input()?;
+            let delta = a - b;
+
+            let di = if max {
+                9.min(9 - delta)
+            } else {
+                1.max(1 - delta)
+            };
+            for (k, v) in [(i, di), (j, di + delta)] {
+                if !(1..=9).contains(&v) || digits[k].is_some_and(|d| d != v) {
+                    return None;
+                }
+                digits[k] = Some(v);
+            }
+        }
+
+        let unconstrained = if max { 9 } else { 1 };
+        Some(digits.iter().map(|d| d.unwrap_or(unconstrained)).collect())
+    }
+}
+
+// Passes that shrink an ALU program without changing the z it ends with, and
+// a disassembler that prints it a block at a time.
+mod optimizer {
+    use super::{Instruction, Operand, Register};
+    use std::collections::HashSet;
+    use std::fmt::Write;
+
+    /// Runs constant propagation and dead-store elimination until neither
+    /// finds anything more to remove. Only z is kept alive at the end.
+    pub fn optimize(program: &[Instruction]) -> Vec<Instruction> {
+        let mut ret = program.to_vec();
+        loop {
+            let next = eliminate_dead_stores(&propagate_constants(&ret));
+            if next == ret {
+                return ret;
+            }
+            ret = next;
+        }
+    }
+
+    /// Replaces register operands whose value is known with the value, and
+    /// drops instructions that leave their register as it was.
+    pub fn propagate_constants(program: &[Instruction]) -> Vec<Instruction> {
+        // every register starts at 0.
+        let mut known = [Some(0); 4];
+        let mut ret = Vec::new();
+
+        for &inst in program {
+            let inst = match inst {
+                Instruction::Inp(r) => {
+                    known[r as usize] = None;
+                    ret.push(inst);
+                    continue;
+                }
+                Instruction::Add(r, o) => Instruction::Add(r, fold(o, &known)),
+                Instruction::Mul(r, o) => Instruction::Mul(r, fold(o, &known)),
+                Instruction::Div(r, o) => Instruction::Div(r, fold(o, &known)),
+                Instruction::Mod(r, o) => Instruction::Mod(r, fold(o, &known)),
+                Instruction::Eql(r, o) => Instruction::Eql(r, fold(o, &known)),
+            };
+
+            let (r, o) = operands(&inst);
+            let before = known[r as usize];
+            let after = evaluate(&inst, before, o);
+
+            // div by 1 stays: it marks the MONAD blocks that don't pop z.
+            let identity = matches!(
+                inst,
+                Instruction::Add(_, Operand::Value(0)) | Instruction::Mul(_, Operand::Value(1))
+            );
+            if identity || (before.is_some() && after == before) {
+                continue;
+            }
+
+            known[r as usize] = after;
+            ret.push(inst);
+        }
+
+        ret
+    }
+
+    /// Drops instructions whose result is overwritten or never read before
+    /// the end of the program, where only z is read.
+    pub fn eliminate_dead_stores(program: &[Instruction]) -> Vec<Instruction> {
+        let mut live = HashSet::from([Register::Z as usize]);
+        let mut keep = vec![true; program.len()];
+
+        for (i, inst) in program.iter().enumerate().rev() {
+            let (r, o) = operands(inst);
+            let r = r as usize;
+
+            // inp always consumes an input and a mod might crash, so those
+            // stay even if nothing reads them. So does a div unless it is by
+            // a non-zero constant.
+            let removable = match inst {
+                Instruction::Inp(_) | Instruction::Mod(_, _) => false,
+                Instruction::Div(_, o) => matches!(o, Operand::Value(v) if *v != 0),
+                _ => true,
+            };
+            if removable && !live.contains(&r) {
+                keep[i] = false;
+                continue;
+            }
+
+            // inp and `mul r 0` set r without reading it.
+            let kills = matches!(
+                inst,
+                Instruction::Inp(_) | Instruction::Mul(_, Operand::Value(0))
+            );
+            if kills {
+                live.remove(&r);
+            } else {
+                live.insert(r);
+            }
+            if let Some(Operand::Register(src)) = o {
+                live.insert(src as usize);
+            }
+        }
+
+        program
+            .iter()
+            .zip(keep)
+            .filter(|(_, k)| *k)
+            .map(|(inst, _)| *inst)
+            .collect()
+    }
+
+    /// The registers that may be read before they are written, for every pc
+    /// plus one entry for the end of the program, where only z is read.
+    pub fn live_registers(program: &[Instruction]) -> Vec<[bool; 4]> {
+        let mut live = vec![[false; 4]; program.len() + 1];
+        live[program.len()][Register::Z as usize] = true;
+
+        for (i, inst) in program.iter().enumerate().rev() {
+            let mut l = live[i + 1];
+            let (r, o) = operands(inst);
+            l[r as usize] = !matches!(
+                inst,
+                Instruction::Inp(_) | Instruction::Mul(_, Operand::Value(0))
+            );
+            if let Some(Operand::Register(src)) = o {
+                l[src as usize] = true;
+            }
+            live[i] = l;
+        }
+
+        live
+    }
+
+    fn fold(o: Operand, known: &[Option<i64>; 4]) -> Operand {
+        match o {
+            Operand::Register(r) => known[r as usize].map_or(o, Operand::Value),
+            _ => o,
+        }
+    }
+
+    fn operands(inst: &Instruction) -> (Register, Option<Operand>) {
+        match *inst {
+            Instruction::Inp(r) => (r, None),
+            Instruction::Add(r, o)
+            | Instruction::Mul(r, o)
+            | Instruction::Div(r, o)
+            | Instruction::Mod(r, o)
+            | Instruction::Eql(r, o) => (r, Some(o)),
+        }
+    }
+
+    // The value r holds after inst, if it can be known. Crashes are unknown.
+    fn evaluate(inst: &Instruction, a: Option<i64>, o: Option<Operand>) -> Option<i64> {
+        let b = match o {
+            Some(Operand::Value(v)) => Some(v),
+            _ => None,
+        };
+
+        match inst {
+            Instruction::Mul(_, _) if b == Some(0) => Some(0),
+            Instruction::Inp(_) => None,
+            Instruction::Add(_, _) => a?.checked_add(b?),
+            Instruction::Mul(_, _) => a?.checked_mul(b?),
+            Instruction::Div(_, _) => a?.checked_div(b?),
+            Instruction::Mod(_, _) => {
+                let (a, b) = (a?, b?);
+                (a >= 0 && b > 0).then(|| a % b)
+            }
+            Instruction::Eql(_, _) => Some((a? == b?) as i64),
+        }
+    }
+
+    /// Splits the program before every inp. Anything ahead of the first inp
+    /// gets a block of its own.
+    pub fn blocks(program: &[Instruction]) -> Vec<&[Instruction]> {
+        let mut starts: Vec<_> = program
+            .iter()
+            .enumerate()
+            .filter(|(_, inst)| matches!(inst, Instruction::Inp(_)))
+            .map(|(i, _)| i)
+            .collect();
+        if starts.first() != Some(&0) {
+            starts.insert(0, 0);
+        }
+        starts.push(program.len());
+
+        starts
+            .windows(2)
+            .map(|w| &program[w[0]..w[1]])
+            .filter(|b| !b.is_empty())
+            .collect()
+    }
+
+    /// The three numbers that set a standard MONAD block apart: what z is
+    /// divided by, what is added to z % 26 before comparing it with the
+    /// digit, and what is added to the digit before pushing it onto z.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MonadBlock {
+        pub div: i64,
+        pub check: i64,
+        pub offset: i64,
+    }
+
+    impl MonadBlock {
+        pub fn parse(block: &[Instruction]) -> Option<MonadBlock> {
+            let value = |i: usize| match block.get(i)? {
+                Instruction::Div(_, Operand::Value(v)) | Instruction::Add(_, Operand::Value(v)) => {
+                    Some(*v)
+                }
+                _ => None,
+            };
+
+            let b = MonadBlock {
+                div: value(4)?,
+                check: value(5)?,
+                offset: value(15)?,
+            };
+            (block == b.instructions()).then_some(b)
+        }
+
+        pub fn instructions(&self) -> Vec<Instruction> {
+            use Instruction::*;
+            use Operand::{Register as R, Value as V};
+            use Register::{W, X, Y, Z};
+
+            vec![
+                Inp(W),
+                Mul(X, V(0)),
+                Add(X, R(Z)),
+                Mod(X, V(26)),
+                Div(Z, V(self.div)),
+                Add(X, V(self.check)),
+                Eql(X, R(W)),
+                Eql(X, V(0)),
+                Mul(Y, V(0)),
+                Add(Y, V(25)),
+                Mul(Y, R(X)),
+                Add(Y, V(1)),
+                Mul(Z, R(Y)),
+                Mul(Y, V(0)),
+                Add(Y, R(W)),
+                Add(Y, V(self.offset)),
+                Mul(Y, R(X)),
+                Add(Z, R(Y)),
+            ]
+        }
+
+        /// Whether some digit can match the check, i.e. z % 26 + check can
+        /// land in 1 through 9.
+        pub fn can_match(&self) -> bool {
+            self.check <= 9 && self.check + 25 >= 1
+        }
+    }
+
+    /// One block per line group, as pseudo-code where the block is a standard
+    /// MONAD block and as one statement per instruction otherwise.
+    pub fn disassemble(program: &[Instruction]) -> String {
+        let mut out = String::new();
+        let mut inputs = 0;
+
+        for (i, block) in blocks(program).iter().enumerate() {
+            writeln!(out, "block {}:", i).unwrap();
+            match MonadBlock::parse(block) {
+                Some(b) => {
+                    writeln!(out, "    w = input[{}]", inputs).unwrap();
+                    inputs += 1;
+
+                    let push = format!("z = z * 26 + w{}", signed(b.offset));
+                    if b.can_match() {
+                        writeln!(out, "    x = z % 26{}", signed(b.check)).unwrap();
+                    }
+                    if b.div != 1 {
+                        writeln!(out, "    z = z / {}", b.div).unwrap();
+                    }
+                    if b.can_match() {
+                        writeln!(out, "    if (x != w) {{ {} }}", push).unwrap();
+                    } else {
+                        writeln!(out, "    {}", push).unwrap();
+                    }
+                }
+                None => {
+                    for inst in block.iter() {
+                        if let Instruction::Inp(_) = inst {
+                            inputs += 1;
+                        }
+                        writeln!(out, "    {}", statement(inst, inputs)).unwrap();
+                    }
+                }
+            }
+        }
+
+        out
+    }
+
+    pub fn signed(v: i64) -> String {
+        match v {
+            0 => String::new(),
+            v if v < 0 => format!(" - {}", -v),
+            v => format!(" + {}", v),
+        }
+    }
+
+    // inputs counts the inp instructions up to and including inst.
+    fn statement(inst: &Instruction, inputs: usize) -> String {
+        match *inst {
+            Instruction::Inp(r) => format!("{} = input[{}]", r, inputs - 1),
+            Instruction::Add(r, o) => format!("{0} = {0} + {1}", r, o),
+            Instruction::Mul(r, Operand::Value(0)) => format!("{} = 0", r),
+            Instruction::Mul(r, o) => format!("{0} = {0} * {1}", r, o),
+            Instruction::Div(r, o) => format!("{0} = {0} / {1}", r, o),
+            Instruction::Mod(r, o) => format!("{0} = {0} % {1}", r, o),
+            Instruction::Eql(r, o) => format!("{0} = ({0} == {1}) as int", r, o),
+        }
+    }
+}
+
+// Turns a program into one bytecode block per input, each a pure function of
+// the registers it reads and the digit it takes. Registers are array indices
+// and operands are split into register and value forms up front, so running
+// a block is a single match per op.
+mod compiled {
+    use super::{optimizer, Instruction, Operand, Register};
+    use std::collections::hash_map::Entry;
+    use std::collections::HashMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    pub type Registers = [i64; 4];
+
+    // keyed on register states, with a hash cheap enough to run on every
+    // state the search visits.
+    type StateMap<K, V> = HashMap<K, V, BuildHasherDefault<StateHasher>>;
+
+    // Multiplies each word in, like rustc's FxHasher. Register states are a
+    // handful of integers, so this beats SipHash by a wide margin.
+    #[derive(Default)]
+    struct StateHasher(u64);
+
+    impl Hasher for StateHasher {
+        fn write(&mut self, bytes: &[u8]) {
+            for chunk in bytes.chunks(8) {
+                let mut word = [0; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                self.write_u64(u64::from_le_bytes(word));
+            }
+        }
+
+        fn write_u64(&mut self, v: u64) {
+            self.0 = (self.0.rotate_left(5) ^ v).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+        }
+
+        fn write_usize(&mut self, v: usize) {
+            self.write_u64(v as u64);
+        }
+
+        fn finish(&self) -> u64 {
+            self.0
+        }
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Op {
+        Inp(usize),
+        AddR(usize, usize),
+        AddV(usize, i64),
+        MulR(usize, usize),
+        MulV(usize, i64),
+        DivR(usize, usize),
+        DivV(usize, i64),
+        ModR(usize, usize),
+        ModV(usize, i64),
+        EqlR(usize, usize),
+        EqlV(usize, i64),
+    }
+
+    #[derive(Clone, Debug)]
+    struct Block {
+        ops: Vec<Op>,
+        // set for a standard MONAD block, which runs as its closed form.
+        monad: Option<optimizer::MonadBlock>,
+        // the registers the block may read before writing them.
+        live: [bool; 4],
+        // for MONAD, the z the block has to start below for z to make it
+        // back down to 0: every block divides z by its div at most.
+        z_limit: Option<i64>,
+    }
+
+    impl Block {
+        fn run(&self, regs: Registers, input: i64) -> Option<Registers> {
+            match &self.monad {
+                Some(b) => run_monad(b, regs, input),
+                None => run(&self.ops, regs, input),
+            }
+        }
+    }
+
+    /// A state's transitions for the digits 1 through 9, cached in a Compiled.
+    #[derive(Clone, Copy, Debug)]
+    pub struct Transitions(usize);
+
+    #[derive(Clone, Debug)]
+    pub struct Compiled {
+        blocks: Vec<Block>,
+        // per block, the transitions from its live registers for each digit
+        // 1 through 9. For MONAD only z is live, so this is keyed on
+        // (block, z, w).
+        cache: Vec<StateMap<Registers, usize>>,
+        transitions: Vec<[Option<Registers>; 9]>,
+    }
+
+    impl Compiled {
+        pub fn new(program: &[Instruction]) -> Self {
+            let live = optimizer::live_registers(program);
+            let limits = z_limits(program);
+            let mut blocks: Vec<Block> = Vec::new();
+            let mut pc = 0;
+
+            for block in optimizer::blocks(program) {
+                let ops = block.iter().map(compile);
+                // anything ahead of the first inp runs with the first block.
+                match blocks.last_mut() {
+                    Some(b) if !b.ops.iter().any(|op| matches!(op, Op::Inp(_))) => {
+                        b.ops.extend(ops);
+                        b.monad = None;
+                    }
+                    _ => blocks.push(Block {
+                        ops: ops.collect(),
+                        monad: optimizer::MonadBlock::parse(block),
+                        live: live[pc],
+                        z_limit: limits.get(&pc).copied(),
+                    }),
+                }
+                pc += block.len();
+            }
+
+            Compiled {
+                cache: vec![StateMap::default(); blocks.len()],
+                transitions: Vec::new(),
+                blocks,
+            }
+        }
+
+        pub fn len(&self) -> usize {
+            self.blocks.len()
+        }
+
+        /// Clears the registers the block overwrites before reading, so
+        /// states that only differ in them compare equal.
+        pub fn live_in(&self, block: usize, mut regs: Registers) -> Registers {
+            for (r, live) in regs.iter_mut().zip(self.blocks[block].live) {
+                if !live {
+                    *r = 0;
+                }
+            }
+            regs
+        }
+
+        /// False when z is too big for the program to ever end with z at 0.
+        pub fn can_finish(&self, block: usize, regs: Registers) -> bool {
+            self.blocks[block]
+                .z_limit
+                .is_none_or(|limit| regs[Register::Z as usize] < limit)
+        }
+
+        /// The registers after running the block with the given input, or
+        /// None if it crashes.
+        pub fn step(&self, block: usize, regs: Registers, input: i64) -> Option<Registers> {
+            self.blocks[block].run(regs, input)
+        }
+
+        /// Like step, but remembers the transitions for every digit the first
+        /// time a state comes up. Only worth it when the same states come up
+        /// again and again. Registers that are never read again come back
+        /// as 0.
+        pub fn cached_step(
+            &mut self,
+            block: usize,
+            regs: Registers,
+            input: i64,
+        ) -> Option<Registers> {
+            let regs = self.live_in(block, regs);
+            if !(1..10).contains(&input) {
+                return self.step(block, regs, input);
+            }
+
+            let (t, _) = self.transitions(block, regs);
+            self.next(t, input)
+        }
+
+        /// Where the cached transitions out of a state are kept, and whether
+        /// this is the first time the state came up.
+        pub fn transitions(&mut self, block: usize, regs: Registers) -> (Transitions, bool) {
+            let regs = self.live_in(block, regs);
+            match self.cache[block].entry(regs) {
+                Entry::Occupied(e) => (Transitions(*e.get()), false),
+                Entry::Vacant(e) => {
+                    let b = &self.blocks[block];
+                    let id = self.transitions.len();
+                    self.transitions
+                        .push([1, 2, 3, 4, 5, 6, 7, 8, 9].map(|d| b.run(regs, d)));
+                    e.insert(id);
+                    (Transitions(id), true)
+                }
+            }
+        }
+
+        /// The registers after the block with the given digit.
+        pub fn next(&self, t: Transitions, input: i64) -> Option<Registers> {
+            self.transitions[t.0][input as usize - 1]
+        }
+    }
+
+    // Covers the blocks after the last one that isn't a MONAD block pushing a
+    // non-negative value, since anything else might shrink z further.
+    pub fn z_limits(program: &[Instruction]) -> HashMap<usize, i64> {
+        let mut limits = HashMap::new();
+        let mut limit = 1i64;
+        let mut pc = program.len();
+
+        for block in optimizer::blocks(program).into_iter().rev() {
+            match optimizer::MonadBlock::parse(block) {
+                Some(b) if b.offset >= 0 && b.div > 0 => limit = limit.saturating_mul(b.div),
+                _ => break,
+            }
+            pc -= block.len();
+            limits.insert(pc, limit);
+        }
+
+        limits
+    }
+
+    fn compile(inst: &Instruction) -> Op {
+        use Operand::{Register as R, Value as V};
+
+        match *inst {
+            Instruction::Inp(a) => Op::Inp(a as usize),
+            Instruction::Add(a, R(b)) => Op::AddR(a as usize, b as usize),
+            Instruction::Add(a, V(v)) => Op::AddV(a as usize, v),
+            Instruction::Mul(a, R(b)) => Op::MulR(a as usize, b as usize),
+            Instruction::Mul(a, V(v)) => Op::MulV(a as usize, v),
+            Instruction::Div(a, R(b)) => Op::DivR(a as usize, b as usize),
+            Instruction::Div(a, V(v)) => Op::DivV(a as usize, v),
+            Instruction::Mod(a, R(b)) => Op::ModR(a as usize, b as usize),
+            Instruction::Mod(a, V(v)) => Op::ModV(a as usize, v),
+            Instruction::Eql(a, R(b)) => Op::EqlR(a as usize, b as usize),
+            Instruction::Eql(a, V(v)) => Op::EqlV(a as usize, v),
+        }
+    }
+
+    // The same as running the block's instructions, but straight from the
+    // three numbers that set it apart.
+    fn run_monad(b: &optimizer::MonadBlock, regs: Registers, input: i64) -> Option<Registers> {
+        let z = regs[Register::Z as usize];
+        if z < 0 {
+            return None;
+        }
+
+        let x = (z % 26 + b.check != input) as i64;
+        let y = (input + b.offset) * x;
+        let z = z.checked_div(b.div)? * (25 * x + 1) + y;
+        Some([input, x, y, z])
+    }
+
+    fn run(ops: &[Op], mut regs: Registers, input: i64) -> Option<Registers> {
+        let modulo = |a: i64, b: i64| (a >= 0 && b > 0).then(|| a % b);
+
+        for op in ops {
+            match *op {
+                Op::Inp(a) => regs[a] = input,
+                Op::AddR(a, b) => regs[a] += regs[b],
+                Op::AddV(a, v) => regs[a] += v,
+                Op::MulR(a, b) => regs[a] *= regs[b],
+                Op::MulV(a, v) => regs[a] *= v,
+                Op::DivR(a, b) => regs[a] = regs[a].checked_div(regs[b])?,
+                Op::DivV(a, v) => regs[a] = regs[a].checked_div(v)?,
+                Op::ModR(a, b) => regs[a] = modulo(regs[a], regs[b])?,
+                Op::ModV(a, v) => regs[a] = modulo(regs[a], v)?,
+                Op::EqlR(a, b) => regs[a] = (regs[a] == regs[b]) as i64,
+                Op::EqlV(a, v) => regs[a] = (regs[a] == v) as i64,
+            }
+        }
+
+        Some(regs)
+    }
+}
+
+/// A line-based debugger for ALU programs. Type `help` at the prompt for the
+/// commands.
+pub mod debugger {
+    use super::{
+        check_model_number, optimizer, parser, Instruction, Interrupt, Machine, ModelNumbers,
+        Register, RegisterState,
+    };
+    use std::collections::{BTreeSet, VecDeque};
+    use std::fs::File;
+    use std::io::{self, BufRead, BufWriter, Write};
+
+    const HELP: &str = "\
+step [n]           run n instructions (default 1)
+continue           run until a breakpoint, an input is needed or the program ends
+break <pc>|inp     stop before the instruction at pc, or before every inp
+delete <pc>|inp    remove a breakpoint
+input <v>...       queue values for upcoming inp instructions
+regs               show the registers
+set <reg|pc> <v>   change a register or the program counter
+list               show the instructions around pc
+disasm             show the program as pseudo-code, one block per inp
+count              count the model numbers the program accepts
+largest <k>        show the k-th largest valid model number, from 0
+smallest <k>       show the k-th smallest valid model number, from 0
+numbers <n>        list the n largest valid model numbers
+check <number>     say whether a model number is valid, and if not why
+trace <file>|off   write every executed instruction to file
+reset              start the program over, keeping breakpoints
+quit";
+
+    /// Reads commands from input until it runs out or `quit`, writing
+    /// responses to out.
+    pub fn run<R: BufRead, W: Write>(source: &str, mut input: R, mut out: W) -> io::Result<()> {
+        let program = match parser::parse(source.trim_end()) {
+            Ok((_, p)) => p,
+            Err(e) => {
+                writeln!(out, "failed to parse program: {}", e)?;
+                return Ok(());
+            }
+        };
+
+        let mut debugger = Debugger::new(program);
+        writeln!(out, "{} instructions loaded", debugger.program.len())?;
+
+        loop {
+            write!(out, "(alu) ")?;
+            out.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                break;
+            }
+
+            match debugger.command(&line) {
+                Ok(Some(msg)) if !msg.is_empty() => writeln!(out, "{}", msg)?,
+                Ok(Some(_)) => (),
+                Ok(None) => break,
+                Err(e) => writeln!(out, "error: {}", e)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub struct Debugger {
+        program: Vec<Instruction>,
+        state: RegisterState,
+        next_input: Option<i64>,
+        inputs: VecDeque<i64>,
+        breakpoints: BTreeSet<usize>,
+        break_on_inp: bool,
+        // set once the program crashes or runs off the end.
+        halted: Option<Interrupt>,
+        trace: Option<BufWriter<File>>,
+        // built on first use, counting searches the whole program.
+        numbers: Option<ModelNumbers>,
+    }
+
+    impl Debugger {
+        pub fn new(program: Vec<Instruction>) -> Self {
+            Debugger {
+                program,
+                state: RegisterState::default(),
+                next_input: None,
+                inputs: VecDeque::new(),
+                breakpoints: BTreeSet::new(),
+                break_on_inp: false,
+                halted: None,
+                trace: None,
+                numbers: None,
+            }
+        }
+
+        #[cfg(test)]
+        pub(super) fn state(&self) -> RegisterState {
+            self.state
+        }
+
+        /// Runs one command. Returns None when the session should end.
+        pub fn command(&mut self, line: &str) -> Result<Option<String>, String> {
+            let words: Vec<_> = line.split_whitespace().collect();
+            let (&cmd, args) = match words.split_first() {
+                Some(w) => w,
+                None => return Ok(Some(String::new())),
+            };
+
+            let msg = match (cmd, args) {
+                ("step" | "s", []) => self.resume(Some(1))?,
+                ("step" | "s", [n]) => self.resume(Some(parse(n)?))?,
+                ("continue" | "c", []) => self.resume(None)?,
+                ("break" | "b", ["inp"]) => {
+                    self.break_on_inp = true;
+                    "breaking before every inp".to_string()
+                }
+                ("break" | "b", [pc]) => {
+                    let pc = self.pc_arg(pc)?;
+                    self.breakpoints.insert(pc);
+                    format!("breakpoint at {}", self.location(pc))
+                }
+                ("delete" | "d", ["inp"]) => {
+                    self.break_on_inp = false;
+                    "no longer breaking on inp".to_string()
+                }
+                ("delete" | "d", [pc]) => match self.breakpoints.remove(&parse(pc)?) {
+                    true => "breakpoint removed".to_string(),
+                    false => return Err(format!("no breakpoint at {}", pc)),
+                },
+                ("input" | "i", values) if !values.is_empty() => {
+                    for v in values {
+                        self.inputs.push_back(parse(v)?);
+                    }
+                    format!("{} inputs queued", self.inputs.len())
+                }
+                ("regs" | "r", []) => self.registers(),
+                ("set", ["pc", pc]) => {
+                    self.state.pc = self.pc_arg(pc)?;
+                    self.halted = None;
+                    self.registers()
+                }
+                ("set", [reg, v]) => {
+                    self.state.set_register(register(reg)?, parse(v)?);
+                    self.registers()
+                }
+                ("list" | "l", []) => self.listing(),
+                ("disasm", []) => optimizer::disassemble(&self.program).trim_end().to_string(),
+                ("count", []) => format!("{} valid model numbers", self.model_numbers().count()),
+                ("largest", [k]) => {
+                    let k = parse(k)?;
+                    let n = self.model_numbers().nth_largest(k);
+                    n.ok_or_else(|| format!("fewer than {} valid model numbers", k + 1))?
+                }
+                ("smallest", [k]) => {
+                    let k = parse(k)?;
+                    let n = self.model_numbers().nth_smallest(k);
+                    n.ok_or_else(|| format!("fewer than {} valid model numbers", k + 1))?
+                }
+                ("numbers", [n]) => {
+                    let n = parse(n)?;
+                    let numbers: Vec<_> = self.model_numbers().iter().take(n).collect();
+                    numbers.join("\n")
+                }
+                ("check", [number]) => match check_model_number(&self.program, number) {
+                    Ok(()) => format!("{} is valid", number),
+                    Err(e) => format!("{} is not valid: {}", number, e),
+                },
+                ("trace", ["off"]) => {
+                    self.trace = None;
+                    "tracing stopped".to_string()
+                }
+                ("trace", [path]) => {
+                    let file = File::create(path).map_err(|e| e.to_string())?;
+                    self.trace = Some(BufWriter::new(file));
+                    format!("tracing to {}", path)
+                }
+                ("reset", []) => {
+                    self.state = RegisterState::default();
+                    self.next_input = None;
+                    self.inputs.clear();
+                    self.halted = None;
+                    "program reset".to_string()
+                }
+                ("help" | "h", []) => HELP.to_string(),
+                ("quit" | "q", []) => return Ok(None),
+                _ => return Err(format!("unknown command '{}', try help", line.trim())),
+            };
+
+            Ok(Some(msg))
+        }
+
+        // Runs until limit instructions have executed, a breakpoint or the
+        // machine interrupts. The instruction at pc always runs, so resuming
+        // from a breakpoint moves past it.
+        fn resume(&mut self, limit: Option<usize>) -> Result<String, String> {
+            if let Some(i) = self.halted {
+                return Err(format!("{}, reset to run again", describe(i)));
+            }
+
+            let mut executed = 0;
+            loop {
+                if limit == Some(executed) {
+                    return Ok(self.stopped("stepped"));
+                }
+
+                let pc = self.state.pc;
+                if executed > 0 {
+                    if self.breakpoints.contains(&pc) {
+                        return Ok(self.stopped("breakpoint"));
+                    }
+                    let at_inp = matches!(self.program.get(pc), Some(Instruction::Inp(_)));
+                    if self.break_on_inp && at_inp {
+                        return Ok(self.stopped("before inp"));
+                    }
+                }
+
+                if self.next_input.is_none() {
+                    self.next_input = self.inputs.pop_front();
+                }
+
+                let mut m = Machine {
+                    program: &self.program,
+                    state: self.state,
+                    next_input: self.next_input,
+                };
+                let interrupt = m.step();
+                self.state = m.state;
+                self.next_input = m.next_input;
+
+                match interrupt {
+                    None => {
+                        executed += 1;
+                        self.write_trace(pc).map_err(|e| e.to_string())?;
+                    }
+                    Some(Interrupt::InputRequred) => {
+                        return Ok(self.stopped("waiting for input"));
+                    }
+                    Some(i) => {
+                        self.halted = Some(i);
+                        return Ok(format!("{}\n{}", describe(i), self.registers()));
+                    }
+                }
+            }
+        }
+
+        fn write_trace(&mut self, pc: usize) -> io::Result<()> {
+            let regs = self.registers();
+            let inst = self.program[pc];
+            match self.trace.as_mut() {
+                Some(t) => {
+                    writeln!(t, "{:>4}  {:<12} {}", pc, inst.to_string(), regs)?;
+                    t.flush()
+                }
+                None => Ok(()),
+            }
+        }
+
+        fn stopped(&self, reason: &str) -> String {
+            format!(
+                "{} at {}\n{}",
+                reason,
+                self.location(self.state.pc),
+                self.registers()
+            )
+        }
+
+        fn location(&self, pc: usize) -> String {
+            match self.program.get(pc) {
+                Some(inst) => format!("pc {}: {}", pc, inst),
+                None => format!("pc {}: end of program", pc),
+            }
+        }
+
+        fn registers(&self) -> String {
+            let s = self.state;
+            format!("w={} x={} y={} z={} pc={}", s.w, s.x, s.y, s.z, s.pc)
+        }
+
+        fn listing(&self) -> String {
+            let pc = self.state.pc;
+            let start = pc.saturating_sub(3);
+            let end = (pc + 4).min(self.program.len());
+
+            let lines: Vec<_> = (start..end)
+                .map(|i| {
+                    let marker = if i == pc { '>' } else { ' ' };
+                    let bp = if self.breakpoints.contains(&i) {
+                        '*'
+                    } else {
+                        ' '
+                    };
+                    format!("{}{} {:>4}  {}", marker, bp, i, self.program[i])
+                })
+                .collect();
+            lines.join("\n")
+        }
+
+        fn model_numbers(&mut self) -> &mut ModelNumbers {
+            let program = &self.program;
+            self.numbers
+                .get_or_insert_with(|| ModelNumbers::new(&optimizer::optimize(program)))
+        }
+
+        // The end of the program is a valid place to stop.
+        fn pc_arg(&self, s: &str) -> Result<usize, String> {
+            let pc: usize = parse(s)?;
+            if pc > self.program.len() {
+                return Err(format!("pc {} is past the end of the program", pc));
+            }
+            Ok(pc)
+        }
+    }
+
+    fn describe(i: Interrupt) -> &'static str {
+        match i {
+            Interrupt::InputRequred => "waiting for input",
+            Interrupt::Crashed => "program crashed",
+            Interrupt::Complete => "program finished",
+        }
+    }
+
+    fn register(s: &str) -> Result<Register, String> {
+        match s {
+            "w" => Ok(Register::W),
+            "x" => Ok(Register::X),
+            "y" => Ok(Register::Y),
+            "z" => Ok(Register::Z),
+            _ => Err(format!("no register named '{}'", s)),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(s: &str) -> Result<T, String> {
+        s.parse().map_err(|_| format!("'{}' is not a number", s))
+    }
+}
+
 mod parser {
     use super::*;
     use crate::lib::combinators::*;
@@ -276,3 +1732,389 @@ mod parser {
         map(params, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::rc::Rc;
+    use symbolic::Expr;
+
+    // A MONAD-style program where each (div, check, offset) block either
+    // pushes digit + offset onto z or pops and compares against it.
+    fn monad(blocks: &[(i64, i64, i64)]) -> Vec<Instruction> {
+        blocks
+            .iter()
+            .flat_map(|&(div, check, offset)| {
+                let b = optimizer::MonadBlock { div, check, offset };
+                b.instructions()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn symbolic_test() {
+        // d2 == d1 + 5 - 7 and d3 == d0 + 3 - 1
+        let program = monad(&[(1, 12, 3), (1, 11, 5), (26, -7, 2), (26, -1, 8)]);
+
+        let ex = symbolic::run(&program).unwrap();
+        assert_eq!(ex.inputs, 4);
+        assert_eq!(**ex.register(Register::Z), Expr::Const(0));
+        let constraints: Vec<_> = ex.constraints.iter().map(|c| c.to_string()).collect();
+        assert_eq!(constraints, vec!["(d1 + -2) == d2", "(d0 + 2) == d3"]);
+
+        assert_eq!(symbolic_model_number(&program, true).unwrap(), "7979");
+        assert_eq!(symbolic_model_number(&program, false).unwrap(), "1313");
+        assert_eq!(dfs_model_number(&program, true), "7979");
+        assert_eq!(dfs_model_number(&program, false), "1313");
+    }
+
+    #[test]
+    fn simplify_test() {
+        let input = |i| Rc::new(Expr::Input(i));
+        let c = |v| Rc::new(Expr::Const(v));
+
+        let low = symbolic::add(input(1), c(5));
+        let z = symbolic::add(
+            symbolic::mul(symbolic::add(input(0), c(3)), c(26)),
+            low.clone(),
+        );
+        assert_eq!(symbolic::modulo(z.clone(), c(26)), Some(low.clone()));
+        assert_eq!(
+            symbolic::div(z.clone(), c(26)).unwrap().to_string(),
+            "(d0 + 3)"
+        );
+        assert_eq!(
+            symbolic::add(symbolic::add(input(2), c(4)), c(-4)),
+            input(2)
+        );
+        assert_eq!(
+            symbolic::eql(&symbolic::add(low, c(7)), &input(3)),
+            Some(c(0))
+        );
+        assert_eq!(symbolic::eql(&z, &input(3)), Some(c(0)));
+        let near = symbolic::add(input(1), c(-2));
+        assert_eq!(symbolic::eql(&near, &input(2)), None);
+        assert_eq!(symbolic::div(z, c(0)), None);
+
+        // sums and products too big for an i64 stay as they are.
+        let big = symbolic::add(c(i64::MAX), c(1));
+        assert_eq!(big.to_string(), format!("({} + 1)", i64::MAX));
+        assert_eq!(big.eval(&[]), None);
+        let big = symbolic::add(symbolic::add(input(0), c(i64::MAX)), c(1));
+        assert_eq!(big.to_string(), format!("((d0 + {}) + 1)", i64::MAX));
+        let big = symbolic::mul(c(i64::MAX), c(2));
+        assert_eq!(big.to_string(), format!("({} * 2)", i64::MAX));
+    }
+
+    #[test]
+    fn optimizer_test() {
+        let program = monad(&[(1, 12, 3), (1, 11, 5), (26, -7, 2), (26, -1, 8)]);
+        let optimized = optimizer::optimize(&program);
+        assert!(optimized.len() < program.len());
+
+        // the first block starts from z = 0, so its mod, div and the add of z
+        // all fold away.
+        let first = optimizer::blocks(&optimized)[0];
+        assert!(!first.iter().any(|i| matches!(i, Instruction::Mod(_, _))));
+        // the rest are still MONAD blocks, down to their div z 1.
+        let rest = &optimizer::blocks(&optimized)[1..];
+        assert!(rest
+            .iter()
+            .all(|b| optimizer::MonadBlock::parse(b).is_some()));
+
+        // every 4 digit input ends with the same z either way.
+        for n in 1111..=9999 {
+            let digits: Vec<i64> = n.to_string().bytes().map(|b| (b - b'0') as i64).collect();
+            if digits.contains(&0) {
+                continue;
+            }
+            assert_eq!(
+                is_valid_model_number(&program, &digits),
+                is_valid_model_number(&optimized, &digits)
+            );
+        }
+
+        let dead = parser::parse("inp w\nadd x w\nmul x 0\nadd x 3\nadd z w\nadd y 2")
+            .unwrap()
+            .1;
+        let expected = parser::parse("inp w\nadd z w").unwrap().1;
+        assert_eq!(optimizer::optimize(&dead), expected);
+
+        // nothing reads x, but the div still crashes when the digit is 5.
+        let crashing = parser::parse("inp w\nadd y w\nadd y -5\ndiv x y\nmul z 0")
+            .unwrap()
+            .1;
+        let optimized = optimizer::optimize(&crashing);
+        let expected = parser::parse("inp w\nadd y w\nadd y -5\ndiv x y")
+            .unwrap()
+            .1;
+        assert_eq!(optimized, expected);
+        for d in 1..10 {
+            assert_eq!(
+                is_valid_model_number(&crashing, &[d]),
+                is_valid_model_number(&optimized, &[d])
+            );
+        }
+        assert!(!is_valid_model_number(&optimized, &[5]));
+
+        // x would overflow, so it stops being known rather than panicking.
+        let overflow = parser::parse("inp w\nadd x 9223372036854775807\nadd x 1\nadd z x")
+            .unwrap()
+            .1;
+        assert_eq!(optimizer::optimize(&overflow), overflow);
+    }
+
+    #[test]
+    fn disassemble_test() {
+        let program = monad(&[(1, 12, 3), (26, -7, 2)]);
+        let expected = "block 0:
+    w = input[0]
+    z = z * 26 + w + 3
+block 1:
+    w = input[1]
+    x = z % 26 - 7
+    z = z / 26
+    if (x != w) { z = z * 26 + w + 2 }
+";
+        assert_eq!(optimizer::disassemble(&program), expected);
+
+        let other = parser::parse("inp z\nmul x 0\neql x z").unwrap().1;
+        let expected = "block 0:
+    z = input[0]
+    x = 0
+    x = (x == z) as int
+";
+        assert_eq!(optimizer::disassemble(&other), expected);
+    }
+
+    #[test]
+    fn debugger_test() {
+        use debugger::Debugger;
+
+        let program = monad(&[(1, 12, 3), (26, -3, 2)]);
+        let mut dbg = Debugger::new(program);
+        let mut run = |cmd: &str| dbg.command(cmd).unwrap().unwrap();
+
+        let disasm = run("disasm");
+        assert!(disasm.starts_with("block 0:\n    w = input[0]\n    z = z * 26 + w + 3"));
+        assert!(disasm.ends_with("if (x != w) { z = z * 26 + w + 2 }"));
+
+        // the second digit has to match the first.
+        assert_eq!(run("count"), "9 valid model numbers");
+        assert_eq!(run("largest 1"), "88");
+        assert_eq!(run("smallest 0"), "11");
+        assert_eq!(run("numbers 3"), "99\n88\n77");
+        assert_eq!(run("check 44"), "44 is valid");
+        assert_eq!(
+            run("check 45"),
+            "45 is not valid: d1 must be d0 = 4, but it is 5"
+        );
+        assert!(run("break inp").contains("every inp"));
+        assert!(run("step").starts_with("waiting for input at pc 0: inp w"));
+        run("input 4");
+        assert!(run("continue").starts_with("before inp at pc 18: inp w"));
+        assert!(run("regs").contains("z=7"));
+
+        // 4 + 3 - 3 matches the second digit.
+        run("input 4");
+        run("break 25");
+        assert!(run("c").starts_with("breakpoint at pc 25: eql x 0"));
+        assert!(run("regs").starts_with("w=4 x=1"));
+
+        // forcing the comparison to fail pushes the digit instead.
+        run("set x 0");
+        assert!(run("continue").starts_with("program finished"));
+        assert_eq!(dbg.state().z, 6);
+        assert!(dbg.command("step").is_err());
+        assert!(dbg.command("set q 1").is_err());
+        assert!(dbg.command("largest 9").is_err());
+
+        let trace = std::env::temp_dir().join("day24_debugger_test.trace");
+        let script = format!(
+            "trace {}\ninput 4 4\ncontinue\nbogus\nquit\nregs\n",
+            trace.display()
+        );
+        let mut out = Vec::new();
+        let source = "inp w\nadd z w\ninp x\nmul z x";
+        debugger::run(source, script.as_bytes(), &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("4 instructions loaded"));
+        assert!(out.contains("program finished\nw=4 x=4 y=0 z=16 pc=4"));
+        assert!(out.contains("error: unknown command 'bogus'"));
+        // nothing runs after quit.
+        assert_eq!(out.matches("(alu) ").count(), 5);
+
+        let trace = std::fs::read_to_string(trace).unwrap();
+        assert_eq!(trace.lines().count(), 4);
+        assert!(trace.lines().last().unwrap().contains("mul z x"));
+    }
+
+    #[test]
+    fn model_numbers_test() {
+        // d2 == d1 - 2 and d3 == d0 + 2, 7 choices each.
+        let program = monad(&[(1, 12, 3), (1, 11, 5), (26, -7, 2), (26, -1, 8)]);
+        let brute_force: Vec<_> = (1111..=9999)
+            .rev()
+            .map(|n: i64| n.to_string())
+            .filter(|n| !n.contains('0'))
+            .filter(|n| check_model_number(&program, n).is_ok())
+            .collect();
+        assert_eq!(brute_force.len(), 49);
+
+        for program in [program.clone(), optimizer::optimize(&program)] {
+            let mut numbers = ModelNumbers::new(&program);
+            assert_eq!(numbers.count(), 49);
+            assert_eq!(numbers.iter().collect::<Vec<_>>(), brute_force);
+            assert_eq!(numbers.nth_largest(1).unwrap(), "7869");
+            assert_eq!(numbers.nth_smallest(0).unwrap(), "1313");
+            assert_eq!(numbers.nth_largest(49), None);
+        }
+
+        // not a MONAD program: valid when both digits match.
+        let program = parser::parse("inp w\ninp z\nmul z -1\nadd z w").unwrap().1;
+        assert_eq!(ModelNumbers::new(&program).count(), 9);
+
+        let program = monad(&[(1, 12, 3), (1, 11, 5), (26, -7, 2), (26, -1, 8)]);
+        assert_eq!(check_model_number(&program, "7979"), Ok(()));
+        let err = |n| check_model_number(&program, n).unwrap_err();
+        assert_eq!(err("7989"), "d2 must be d1 - 2 = 7, but it is 8");
+        assert_eq!(err("7971"), "d3 must be d0 + 2 = 9, but it is 1");
+        assert_eq!(err("7909"), "d2 is 0, digits run from 1 through 9");
+        assert_eq!(err("797"), "expected 4 digits, got 3");
+        assert_eq!(err("79x9"), "'x' is not a digit");
+    }
+
+    #[test]
+    fn compiled_test() {
+        // the setup ahead of the first inp joins the first block.
+        let source = "add y 3\ninp w\nmul w y\ninp x\nadd z w\nmod z x\ninp w\ndiv z w";
+        let program = parser::parse(source).unwrap().1;
+        let mut compiled = compiled::Compiled::new(&program);
+        assert_eq!(compiled.len(), 3);
+
+        let regs = compiled.step(0, [0; 4], 4).unwrap();
+        assert_eq!(regs, [12, 0, 3, 0]);
+        assert_eq!(compiled.step(1, regs, 5), Some([12, 5, 3, 2]));
+        // nothing reads y again.
+        let regs = compiled.cached_step(1, regs, 5).unwrap();
+        assert_eq!(regs, [12, 5, 0, 2]);
+        assert_eq!(compiled.cached_step(1, [12, 0, 7, 0], 5), Some(regs));
+        assert_eq!(compiled.step(2, regs, 2), Some([2, 5, 0, 1]));
+        // only z matters to the last block, w is overwritten by its inp.
+        assert_eq!(compiled.live_in(2, regs), [0, 0, 0, 2]);
+
+        let crash = compiled::Compiled::new(&parser::parse("inp x\nmod z x").unwrap().1);
+        assert_eq!(crash.step(0, [0, 0, 0, -1], 3), None);
+
+        let program = monad(&[(1, 12, 3), (1, 11, 5), (26, -7, 2), (26, -1, 8)]);
+        let compiled = compiled::Compiled::new(&program);
+        assert!(compiled.can_finish(2, [0, 0, 0, 26 * 26 - 1]));
+        assert!(!compiled.can_finish(2, [0, 0, 0, 26 * 26]));
+        assert!(!compiled.can_finish(3, [0, 0, 0, 26]));
+
+        // MONAD blocks run as their closed form, which has to agree with the
+        // instructions, crashes included.
+        for (block, pc) in [(0, 0), (2, 36)] {
+            for z in [-1, 0, 5, 25, 26, 27, 26 * 26 + 3] {
+                for w in 1..10 {
+                    let state = RegisterState {
+                        z,
+                        pc,
+                        ..Default::default()
+                    };
+                    let expected = match run_machine(&program, state, w) {
+                        InterruptState::InputRequred(s) | InterruptState::Complete(s) => {
+                            Some([s.w, s.x, s.y, s.z])
+                        }
+                        InterruptState::Crashed => None,
+                    };
+                    assert_eq!(compiled.step(block, [0, 0, 0, z], w), expected);
+                }
+            }
+        }
+    }
+
+    // The search as it was before programs were compiled, plus the same z
+    // pruning the compiled search does, so timing the two only measures the
+    // compilation and the transition cache.
+    fn interpreted_model_number(program: &[Instruction], max: bool) -> String {
+        fn rec(
+            program: &[Instruction],
+            limits: &HashMap<usize, i64>,
+            path: &mut Vec<i64>,
+            seen: &mut HashSet<RegisterState>,
+            state: RegisterState,
+            max: bool,
+        ) -> bool {
+            let too_big = limits.get(&state.pc).is_some_and(|&limit| state.z >= limit);
+            if too_big || seen.contains(&state) {
+                return false;
+            }
+            for i in 1..10 {
+                let i = if max { 10 - i } else { i };
+                path.push(i);
+                match run_machine(program, state, i) {
+                    InterruptState::InputRequred(s) => {
+                        let ok = rec(program, limits, path, seen, s, max);
+                        seen.insert(s);
+                        if ok {
+                            return true;
+                        }
+                    }
+                    InterruptState::Crashed => (),
+                    InterruptState::Complete(s) if s.z == 0 => return true,
+                    InterruptState::Complete(_) => (),
+                }
+                path.pop();
+            }
+            false
+        }
+
+        let mut path = Vec::new();
+        rec(
+            program,
+            &compiled::z_limits(program),
+            &mut path,
+            &mut HashSet::new(),
+            RegisterState::default(),
+            max,
+        );
+        to_model_number(&path)
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --release"]
+    fn dfs_benchmark() {
+        let program = optimizer::optimize(&monad(&[
+            (1, 13, 6),
+            (1, 11, 11),
+            (1, 12, 5),
+            (1, 10, 6),
+            (1, 14, 8),
+            (26, -1, 14),
+            (1, 14, 9),
+            (26, -16, 4),
+            (26, -8, 7),
+            (1, 12, 13),
+            (26, -16, 11),
+            (26, -13, 11),
+            (26, -6, 6),
+            (26, -6, 1),
+        ]));
+
+        let time = |f: &dyn Fn() -> String| {
+            let start = std::time::Instant::now();
+            (f(), start.elapsed())
+        };
+        let (slow, interpreted) = time(&|| interpreted_model_number(&program, true));
+        let (fast, compiled) = time(&|| dfs_model_number(&program, true));
+        println!("interpreted {:?}, compiled {:?}", interpreted, compiled);
+
+        // with the pruning the same on both sides this is only the bytecode,
+        // the closed form and the cache, which come to about 3-4x here.
+        assert_eq!(slow, fast);
+        assert!(compiled * 2 <= interpreted);
+    }
+}
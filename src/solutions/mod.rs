@@ -1,4 +1,6 @@
 use std::collections::HashMap;
+use std::io;
+use std::time::Duration;
 
 mod day01;
 mod day02;
@@ -71,3 +73,82 @@ pub fn run(day: usize, problem: usize, input: &str) -> Option<String> {
     let solutions = init_solutions();
     solutions.get(&(day, problem)).map(|&f| f(input))
 }
+
+/// Reads day 8's displays, on a custom font given as its segment count and
+/// the segments of each digit if there is one.
+pub fn read_displays(input: &str, font: Option<(usize, &[&str])>) -> Result<String, String> {
+    let font = match font {
+        Some((segments, digits)) => Some(day08::Font::new(segments, digits)?),
+        None => None,
+    };
+    day08::read_displays(input, font.as_ref())
+}
+
+/// Every explode and split in adding up day 18's homework.
+pub fn snailfish_reductions(input: &str) -> Result<String, String> {
+    day18::reduction_report(input)
+}
+
+/// The magnitude of day 18's homework summed with the tree or flat backend.
+pub fn snailfish_sum(input: &str, backend: &str) -> Result<String, String> {
+    day18::sum_report(input, backend.parse()?)
+}
+
+/// The k pairs of day 18's homework with the largest sums, found with the tree
+/// or flat backend.
+pub fn best_snailfish_pairs(input: &str, k: usize, backend: &str) -> Result<String, String> {
+    day18::best_pairs_report(input, k, backend.parse()?)
+}
+
+/// How long each of day 18's backends takes on the homework.
+pub fn compare_snailfish_backends(input: &str) -> Result<String, String> {
+    day18::compare_backends(input)
+}
+
+/// Day 19's scanners as an alignment tree, or as CSV or PLY for other tools.
+pub fn export_scanners(input: &str, format: &str) -> Result<String, String> {
+    day19::export(input, format)
+}
+
+/// Day 19's scanner poses in the coordinates of the scanner with the given id.
+pub fn scanner_poses(input: &str, reference: i32) -> Result<String, String> {
+    day19::relative_poses(input, reference)
+}
+
+/// Plots a day 17 launch at velocity, or every launch that hits as SVG.
+pub fn trajectory_report(input: &str, velocity: Option<[i64; 2]>) -> Result<String, String> {
+    day17::trajectory_report(input, velocity)
+}
+
+/// Replays day 22's reboot step by step with the fragments, signed or
+/// compressed backend, and says what became of point.
+pub fn reactor_report(
+    input: &str,
+    point: Option<[i64; 3]>,
+    backend: &str,
+) -> Result<String, String> {
+    day22::reactor_report(input, point, backend.parse()?)
+}
+
+/// Steps through an ALU program from day 24 interactively on stdin/stdout.
+pub fn debug_alu(program: &str) -> io::Result<()> {
+    day24::debugger::run(program, io::stdin().lock(), io::stdout())
+}
+
+/// Compares how many states day 23's search visits with and without its
+/// heuristic and deduplication.
+pub fn amphipod_search_stats(input: &str, unfold: bool) -> Result<String, String> {
+    day23::compare_searches(input, unfold)
+}
+
+/// The odds of day 21's Dirac dice game for the puzzle's players, under part
+/// two's rules or [board, target, die_sides, rolls].
+pub fn dirac_report(input: &str, rules: Option<[u32; 4]>) -> Result<String, &'static str> {
+    day21::dirac_report(input, rules)
+}
+
+/// Plays back the cheapest way to organize day 23's amphipods on stdout.
+pub fn replay_amphipods(input: &str, unfold: bool) -> io::Result<()> {
+    let delay = Duration::from_millis(400);
+    day23::animate(input, unfold, io::stdout(), delay)
+}